@@ -0,0 +1,4500 @@
+use clap::ValueEnum;
+use minilp::{ComparisonOp, OptimizationDirection, Problem, Solution, Variable};
+use regex::Regex;
+use serde::{ser::SerializeStruct, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    fmt::Display,
+    fs, io,
+    path::Path,
+};
+use thiserror::Error;
+
+/// Relative cost of each raw resource when choosing between alternate recipes, derived from
+/// the total amount of each resource that can be extracted across the map
+const RESOURCE_WEIGHTS: [(&str, f64); 12] = [
+    ("Iron Ore", 1.0),
+    ("Limestone", 1.33),
+    ("Coal", 2.18),
+    ("Copper Ore", 2.5),
+    ("Caterium Ore", 6.14),
+    ("Raw Quartz", 6.82),
+    ("Crude Oil", 7.31),
+    ("Bauxite", 7.49),
+    ("Nitrogen Gas", 7.68),
+    ("Sulfur", 8.53),
+    ("Uranium", 43.86),
+    ("Water", 0.0),
+];
+
+const BASIC_INGREDIENTS: [&str; 11] = [
+    "Coal",
+    "Limestone",
+    "Iron Ore",
+    "Copper Ore",
+    "Bauxite",
+    "Caterium Ore",
+    "Raw Quartz",
+    "Sulfur",
+    "Crude Oil",
+    "Water",
+    "Nitrogen Gas",
+];
+
+const FLUIDS: [&str; 10] = [
+    "Water",
+    "Crude Oil",
+    "Heavy Oil Residue",
+    "Fuel",
+    "Turbofuel",
+    "Liquid Biofuel",
+    "Alumina Solution",
+    "Sulfuric Acid",
+    "Nitric Acid",
+    "Nitrogen Gas",
+];
+
+/// Items per minute carried by each tier of conveyor belt
+const BELT_SPEEDS: [f32; 6] = [60.0, 120.0, 270.0, 480.0, 780.0, 1200.0];
+/// Fluid per minute carried by each tier of pipeline
+const PIPE_SPEEDS: [f32; 2] = [300.0, 600.0];
+
+/// Everything that can go wrong while loading configs, parsing a request or planning a factory
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("{file} couldn't be read: {source}")]
+    ReadFailed { file: String, source: io::Error },
+    #[error("{file} couldn't be written: {source}")]
+    WriteFailed { file: String, source: io::Error },
+    #[error("{file} is in an invalid format: {source}")]
+    InvalidConfig {
+        file: String,
+        source: serde_json::Error,
+    },
+    #[error("{file} is in an invalid format: {source}")]
+    InvalidPlanFile {
+        file: String,
+        source: toml::de::Error,
+    },
+    #[error("{0} isn't a plan file; plan files end in .toml or .json")]
+    UnknownPlanFormat(String),
+    #[error("{file} is in an invalid format: it isn't valid UTF-16")]
+    InvalidEncoding { file: String },
+    #[error("{file} removes '{recipe}', which isn't a recipe")]
+    UnknownRemovedRecipe { file: String, recipe: String },
+    #[error("{file} refers to {item}, which isn't an item")]
+    UnknownDocsItem { file: String, item: String },
+    #[error("{file} gives {item} an amount of '{amount}', which isn't a number")]
+    InvalidDocsAmount {
+        file: String,
+        item: String,
+        amount: String,
+    },
+    #[error("only one recipe config can be written to")]
+    MultipleRecipeConfigs,
+    #[error("'{input}' is invalid; expected {expected}")]
+    InvalidSyntax {
+        input: String,
+        expected: &'static str,
+    },
+    #[error("'{name}' isn't a known product{}", did_you_mean(suggestions))]
+    UnknownProduct {
+        name: String,
+        /// The closest product names, best first
+        suggestions: Vec<String>,
+    },
+    #[error("{file} makes '{alias}' an alias for {product}, which isn't a known product")]
+    InvalidAlias {
+        file: String,
+        alias: String,
+        product: String,
+    },
+    #[error("no products are wanted; the want list is empty")]
+    NothingWanted,
+    #[error("{0} is not in the want, have or use list")]
+    NotRequested(String),
+    #[error("'{0}' is missing a clock speed")]
+    MissingClockSpeed(String),
+    #[error("clock speeds must be between 1% and {MAX_CLOCK_SPEED}%, not {0}%")]
+    InvalidClockSpeed(f32),
+    #[error("'{0}' doesn't say which resource it's for")]
+    MissingResource(String),
+    #[error("{0} has no recipes to choose from")]
+    NoRecipes(String),
+    #[error("{product} has no recipe {number}; its recipes are numbered 1 to {count}")]
+    InvalidRecipeIndex {
+        product: String,
+        number: usize,
+        count: usize,
+    },
+    #[error("{product} has no recipe named '{name}'{}", its_recipes(recipes))]
+    UnknownRecipe {
+        product: String,
+        name: String,
+        /// The names of the product's recipes, in `--list-recipes` order
+        recipes: Vec<String>,
+    },
+    #[error(
+        "{product} can't be produced, as its recipes form a loop with no outside input: {}",
+        cycle.join(" -> ")
+    )]
+    ClosedLoop { product: String, cycle: Vec<String> },
+    #[error("the recipe loop through {0} does not converge")]
+    DivergentLoop(String),
+    #[error("couldn't listen on {address}: {source}")]
+    ServeFailed {
+        address: String,
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    #[error("{failed} of {total} plan requests failed")]
+    BatchFailed { failed: usize, total: usize },
+    #[error("the factory could not be balanced: {0}")]
+    Unbalanced(#[from] minilp::Error),
+}
+
+fn did_you_mean(suggestions: &[String]) -> String {
+    match suggestions {
+        [] => String::new(),
+        [suggestion] => format!("; did you mean {}?", suggestion),
+        [rest @ .., last] => format!("; did you mean {} or {}?", rest.join(", "), last),
+    }
+}
+
+fn its_recipes(recipes: &[String]) -> String {
+    match recipes {
+        [] => String::new(),
+        [recipe] => format!("; its only recipe is {}", recipe),
+        [rest @ .., last] => format!("; its recipes are {} and {}", rest.join(", "), last),
+    }
+}
+
+trait DefaultDict<K, V>
+where
+    K: Eq + PartialEq + std::hash::Hash + Clone,
+    V: Default,
+{
+    fn get_default(&mut self, key: &K) -> &mut V;
+}
+
+impl<K, V> DefaultDict<K, V> for HashMap<K, V>
+where
+    K: Eq + PartialEq + std::hash::Hash + Clone,
+    V: Default,
+{
+    fn get_default(&mut self, key: &K) -> &mut V {
+        self.entry(key.clone()).or_default()
+    }
+}
+
+impl<K, V> DefaultDict<K, V> for BTreeMap<K, V>
+where
+    K: Eq + PartialEq + Ord + std::hash::Hash + Clone,
+    V: Default,
+{
+    fn get_default(&mut self, key: &K) -> &mut V {
+        self.entry(key.clone()).or_default()
+    }
+}
+
+#[derive(Debug, Clone)]
+struct IndexedMap<K, V>
+where
+    K: std::hash::Hash + Eq + PartialEq,
+{
+    map: HashMap<K, Vec<V>>,
+    index: HashMap<K, usize>,
+}
+
+impl<K, V> IndexedMap<K, V>
+where
+    K: std::hash::Hash + Eq + PartialEq,
+{
+    fn new() -> IndexedMap<K, V> {
+        IndexedMap {
+            map: HashMap::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        self.get_index(key)
+            .and_then(|index| self.map.get(key)?.get(index))
+    }
+
+    /// The index of the value chosen for `key`; indexes are checked when they're chosen, so this
+    /// may be out of range if one was inserted without checking
+    fn get_index(&self, key: &K) -> Option<usize> {
+        self.map
+            .get(key)
+            .map(|_| *self.index.get(key).unwrap_or(&0))
+    }
+}
+
+impl<K, V, I> From<I> for IndexedMap<K, V>
+where
+    K: std::hash::Hash + Eq + PartialEq + Clone,
+    I: Iterator<Item = (K, V)>,
+{
+    fn from(value: I) -> Self {
+        let mut map = IndexedMap::new();
+        for (key, val) in value {
+            map.map.get_default(&key).push(val);
+        }
+        map
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Recipe {
+    /// In-game name of the recipe, e.g. "Alternate: Pure Iron Ingot"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub machine: String,
+    pub ingredients: Vec<(String, f32)>,
+    pub products: Vec<(String, f32)>,
+    /// Power drawn per machine in MW, for recipes that draw a different amount than their machine normally does.
+    /// Negative for generators
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub power: Option<f32>,
+    /// Milestone or MAM research that unlocks the recipe, if it isn't available from the start
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unlock: Option<String>,
+    /// Config file the recipe was loaded from
+    #[serde(skip)]
+    pub config: String,
+}
+
+/// An entry in a recipe config; either a recipe to add, which replaces any earlier recipe with the
+/// same name, or the name of an earlier recipe to remove
+#[derive(Debug)]
+enum RecipeEntry {
+    Remove { remove: String },
+    Recipe(Recipe),
+}
+
+impl<'de> Deserialize<'de> for RecipeEntry {
+    /// Entries are told apart by their `remove` key, so that a malformed recipe reports what's
+    /// wrong with it rather than that it matched neither kind of entry
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(deny_unknown_fields)]
+        struct Remove {
+            remove: String,
+        }
+
+        let entry = serde_json::Value::deserialize(deserializer)?;
+        if entry.get("remove").is_some() {
+            Remove::deserialize(entry)
+                .map(|Remove { remove }| RecipeEntry::Remove { remove })
+                .map_err(serde::de::Error::custom)
+        } else {
+            Recipe::deserialize(entry)
+                .map(RecipeEntry::Recipe)
+                .map_err(serde::de::Error::custom)
+        }
+    }
+}
+
+/// The milestones, MAM research & alternate recipes a player has unlocked
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+struct Profile {
+    #[serde(default)]
+    milestones: Vec<String>,
+    #[serde(default)]
+    research: Vec<String>,
+    #[serde(default)]
+    alternates: Vec<String>,
+}
+
+impl Profile {
+    /// Whether a recipe can be built; alternates have to be listed by name, and any other recipe
+    /// with an unlock needs its milestone or research to be listed
+    fn unlocks(&self, recipe: &Recipe) -> bool {
+        let alternate_unlocked = !recipe.is_alternate()
+            || self
+                .alternates
+                .iter()
+                .any(|alternate| recipe.is_named(&alternate.to_lowercase()));
+        let unlock_reached = recipe.unlock.as_ref().is_none_or(|unlock| {
+            self.milestones
+                .iter()
+                .chain(self.research.iter())
+                .any(|unlocked| unlocked.eq_ignore_ascii_case(unlock))
+        });
+        alternate_unlocked && unlock_reached
+    }
+}
+
+pub const POWER_SUFFIX: &str = " Power";
+
+#[derive(Deserialize, Clone, Debug)]
+struct Generator {
+    name: String,
+    /// Power produced per generator in MW
+    power: f32,
+    /// Water consumed per generator
+    #[serde(default)]
+    water: f32,
+    fuels: Vec<Fuel>,
+}
+
+#[derive(Deserialize, Clone, Debug)]
+struct Fuel {
+    item: String,
+    /// Fuel burned per generator
+    rate: f32,
+    #[serde(default)]
+    byproducts: Vec<(String, f32)>,
+}
+
+impl Generator {
+    /// The name of the product representing power from this generator, in MW
+    fn product(&self) -> String {
+        format!("{}{}", self.name, POWER_SUFFIX)
+    }
+
+    /// One recipe per fuel the generator can burn
+    fn recipes(&self) -> Vec<Recipe> {
+        self.fuels
+            .iter()
+            .map(|fuel| Recipe {
+                name: Some(format!("{} ({})", self.name, fuel.item)),
+                machine: self.name.clone(),
+                ingredients: [(fuel.item.clone(), fuel.rate)]
+                    .into_iter()
+                    .chain((self.water > 0.0).then(|| ("Water".to_string(), self.water)))
+                    .collect(),
+                products: [(self.product(), self.power)]
+                    .into_iter()
+                    .chain(fuel.byproducts.iter().cloned())
+                    .collect(),
+                power: Some(-self.power),
+                unlock: None,
+                config: String::new(),
+            })
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemForm {
+    Solid,
+    Liquid,
+    Gas,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Item {
+    pub name: String,
+    pub form: ItemForm,
+    pub stack_size: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Machine {
+    pub name: String,
+    /// Power drawn per machine in MW
+    pub power: f32,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Source {
+    Recipe {
+        machine: String,
+        /// zero-based index of the recipe, written out as its number from `--list-recipes`
+        #[serde(serialize_with = "serialize_recipe_number")]
+        recipe: usize,
+        machine_quantity: f32,
+        /// clock speed of each machine, as a percentage
+        clock: f32,
+        power: f32,
+        byproducts: Vec<(String, f32)>,
+        ingredients: Vec<Product>,
+    },
+    Supply,
+    Byproduct,
+    /// Fed back from a production line further up the tree, as part of a recipe loop
+    Cycle,
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Product {
+    pub name: String,
+    pub sources: Vec<(f32, Source)>,
+}
+
+impl Product {
+    /// Total quantity of `name` fed back into this node's subtree by recipe loops
+    fn recycled(&self, name: &String) -> f32 {
+        self.sources
+            .iter()
+            .map(|(quantity, source)| match source {
+                Source::Recipe { ingredients, .. } => ingredients
+                    .iter()
+                    .map(|ingredient| ingredient.recycled(name))
+                    .sum(),
+                Source::Cycle if self.name == *name => *quantity,
+                _ => 0.0,
+            })
+            .sum()
+    }
+}
+
+impl Display for Product {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        ProductDisplay {
+            product: self.clone(),
+            indent: 0,
+            logistics: None,
+        }
+        .fmt(f)
+    }
+}
+
+struct ProductDisplay<'a> {
+    product: Product,
+    indent: usize,
+    /// annotate every line with the belts or pipes needed to carry it
+    logistics: Option<&'a Logistics>,
+}
+
+impl ProductDisplay<'_> {
+    fn throughput(&self, item: &str, quantity: f32) -> String {
+        if item.ends_with(POWER_SUFFIX) {
+            return String::new();
+        }
+        self.logistics.map_or_else(String::new, |logistics| {
+            format!(" [{}]", logistics.describe(item, quantity))
+        })
+    }
+}
+
+impl Display for ProductDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (quantity, source) in &self.product.sources {
+            match source {
+                Source::Recipe {
+                    machine,
+                    machine_quantity,
+                    clock,
+                    ingredients,
+                    byproducts,
+                    ..
+                } => {
+                    // flag any machine that can't be fed or emptied by a single belt or pipe
+                    let overloaded = self.logistics.map_or_else(String::new, |logistics| {
+                        let overloaded = machine_flows(&self.product.name, *quantity, source)
+                            .into_iter()
+                            .filter(|(item, flow)| {
+                                *flow > logistics.limit(item) * (1.0 + FLOW_EPSILON as f32)
+                            })
+                            .map(|(item, _)| item)
+                            .collect::<Vec<_>>();
+                        if overloaded.is_empty() {
+                            String::new()
+                        } else {
+                            format!(
+                                " (exceeds one belt or pipe per machine for {}!)",
+                                overloaded.join(", ")
+                            )
+                        }
+                    });
+                    writeln!(
+                        f,
+                        "{:>indent$} * {:.2} {}: {:.2} {}{}{}{}",
+                        "",
+                        quantity,
+                        self.product.name,
+                        machine_quantity,
+                        machine,
+                        ClockDisplay(*clock),
+                        self.throughput(&self.product.name, *quantity),
+                        overloaded,
+                        indent = self.indent,
+                    )?;
+                    for sub_product in ingredients.iter() {
+                        ProductDisplay {
+                            product: sub_product.clone(),
+                            indent: self.indent + 2,
+                            logistics: self.logistics,
+                        }
+                        .fmt(f)?;
+                    }
+                    for (byproduct, byproduct_quantity) in byproducts.iter() {
+                        writeln!(
+                            f,
+                            "{:>indent$} < {:.2} {}{}",
+                            "",
+                            byproduct_quantity,
+                            byproduct,
+                            self.throughput(byproduct, *byproduct_quantity),
+                            indent = self.indent,
+                        )?;
+                    }
+                }
+                Source::Supply => {
+                    writeln!(
+                        f,
+                        "{:>indent$} - {:.2} {}{}",
+                        "",
+                        quantity,
+                        self.product.name,
+                        self.throughput(&self.product.name, *quantity),
+                        indent = self.indent,
+                    )?;
+                }
+                Source::Byproduct => {
+                    writeln!(
+                        f,
+                        "{:>indent$} > {:.2} {}{}",
+                        "",
+                        quantity,
+                        self.product.name,
+                        self.throughput(&self.product.name, *quantity),
+                        indent = self.indent,
+                    )?;
+                }
+                Source::Cycle => {
+                    writeln!(
+                        f,
+                        "{:>indent$} ~ {:.2} {}{}",
+                        "",
+                        quantity,
+                        self.product.name,
+                        self.throughput(&self.product.name, *quantity),
+                        indent = self.indent,
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How much of a product one of its recipes makes across the factory, and the power it draws
+#[derive(Debug, Clone, Default)]
+pub struct RecipeUsage {
+    /// In-game name of the recipe, or the product's name if the recipe has none
+    pub name: String,
+    pub quantity: f32,
+    pub power: f32,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct DependencyResolutionTotals {
+    pub inputs: HashMap<String, f32>,
+    pub byproduct_inputs: HashMap<String, f32>,
+    pub intermediate_ingredients: HashMap<String, f32>,
+    pub outputs: HashMap<String, f32>,
+    pub byproducts: HashMap<String, f32>,
+    pub machines: HashMap<String, HashMap<String, f32>>,
+    pub clocks: HashMap<String, HashMap<String, f32>>,
+    pub power: HashMap<String, HashMap<String, f32>>,
+    #[serde(serialize_with = "serialize_recipe_totals")]
+    pub recipes: HashMap<String, HashMap<(usize, String), RecipeUsage>>,
+    pub extraction: BTreeMap<String, ExtractionPlan>,
+}
+
+impl DependencyResolutionTotals {
+    fn new() -> DependencyResolutionTotals {
+        DependencyResolutionTotals {
+            inputs: HashMap::new(),
+            byproduct_inputs: HashMap::new(),
+            intermediate_ingredients: HashMap::new(),
+            outputs: HashMap::new(),
+            byproducts: HashMap::new(),
+            machines: HashMap::new(),
+            clocks: HashMap::new(),
+            power: HashMap::new(),
+            recipes: HashMap::new(),
+            extraction: BTreeMap::new(),
+        }
+    }
+
+    pub fn machine_power(&self, machine: &String) -> f32 {
+        self.power
+            .get(machine)
+            .map_or(0.0, |machine_power| machine_power.values().sum())
+    }
+
+    pub fn total_power(&self) -> f32 {
+        self.power
+            .values()
+            .flat_map(|machine_power| machine_power.values())
+            .filter(|power| **power > 0.0)
+            .copied()
+            .chain(self.extraction.values().map(|plan| plan.power()))
+            // summing nothing as floats gives -0.0, so start from 0.0 instead
+            .fold(0.0, |total, power| total + power)
+    }
+
+    pub fn power_generated(&self) -> f32 {
+        -self
+            .power
+            .values()
+            .flat_map(|machine_power| machine_power.values())
+            .filter(|power| **power < 0.0)
+            .sum::<f32>()
+    }
+
+    /// Power shards needed to overclock every machine, assuming each production line is built
+    /// from whole machines running at the line's clock speed, plus one slower machine for the rest
+    pub fn power_shards(&self) -> u32 {
+        self.machines
+            .iter()
+            .flat_map(|(machine, machine_products)| {
+                machine_products.iter().map(move |(product, quantity)| {
+                    line_power_shards(*quantity, self.clocks[machine][product])
+                })
+            })
+            .sum::<u32>()
+            + self
+                .extraction
+                .values()
+                .flat_map(|plan| plan.extractors.iter())
+                .map(|extractor| extractor.count * power_shards(extractor.clock))
+                .sum::<u32>()
+    }
+
+    /// Plan the extractors needed to supply every raw resource the factory takes in
+    fn plan_extraction(&mut self, nodes: &HashMap<String, ResourceNodes>, miner: MinerTier) {
+        self.extraction = self
+            .inputs
+            .iter()
+            .filter_map(|(resource, demand)| {
+                plan_resource_extraction(resource, *demand, nodes.get(resource), miner)
+                    .map(|plan| (resource.clone(), plan))
+            })
+            .collect();
+    }
+
+    fn from(dependency_trees: &[Product]) -> DependencyResolutionTotals {
+        let mut totals = DependencyResolutionTotals::new();
+        totals.tally_trees(dependency_trees);
+        totals
+    }
+
+    fn tally_trees(&mut self, dependency_trees: &[Product]) {
+        dependency_trees.iter().for_each(|product| {
+            // tally outputs, less whatever is fed back into the product's own recipe loops
+            for (quantity, source) in &product.sources {
+                *self.outputs.get_default(&product.name) += quantity;
+                if let Source::Byproduct = source {
+                    *self.byproduct_inputs.get_default(&product.name) += quantity;
+                }
+            }
+            *self.outputs.get_default(&product.name) -= product.recycled(&product.name);
+
+            // tally sub-nodes
+            self.tally_node(product);
+        })
+    }
+
+    fn tally_node(&mut self, node: &Product) {
+        for (quantity, source) in &node.sources {
+            if let Source::Recipe {
+                machine,
+                recipe,
+                machine_quantity,
+                clock,
+                power,
+                ingredients,
+                byproducts,
+            } = source
+            {
+                // tally machine counts, clock speeds & power usage
+                *self.machines.get_default(machine).get_default(&node.name) += machine_quantity;
+                self.clocks
+                    .get_default(machine)
+                    .insert(node.name.clone(), *clock);
+                *self.power.get_default(machine).get_default(&node.name) += power;
+
+                // tally recipes used
+                let usage = self
+                    .recipes
+                    .get_default(&node.name)
+                    .get_default(&(*recipe, machine.clone()));
+                usage.quantity += quantity;
+                usage.power += power;
+
+                // tally byproducts
+                byproducts.iter().for_each(|(product, quantity)| {
+                    *self.byproducts.get_default(product) += quantity
+                });
+
+                // tally intermediate ingredients, inputs, and sub-nodes
+                ingredients.iter().for_each(|product| {
+                    for (quantity, sub_source) in &product.sources {
+                        match sub_source {
+                            Source::Recipe { .. } => {
+                                *self.intermediate_ingredients.get_default(&product.name) +=
+                                    quantity;
+                            }
+                            Source::Supply => {
+                                *self.inputs.get_default(&product.name) += quantity;
+                            }
+                            Source::Byproduct => {
+                                *self.byproduct_inputs.get_default(&product.name) += quantity;
+                            }
+                            Source::Cycle => (),
+                        }
+                    }
+                    self.tally_node(product);
+                });
+            }
+        }
+    }
+}
+
+fn serialize_recipe_number<S: Serializer>(
+    recipe: &usize,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(*recipe as u64 + 1)
+}
+
+fn serialize_optional_recipe_number<S: Serializer>(
+    recipe: &Option<usize>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match recipe {
+        Some(recipe) => serialize_recipe_number(recipe, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Recipe totals are keyed by recipe index & machine, which can't be JSON keys, so each product's
+/// recipes are written out as a list instead
+fn serialize_recipe_totals<S: Serializer>(
+    recipes: &HashMap<String, HashMap<(usize, String), RecipeUsage>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct RecipeTotal<'a> {
+        recipe: usize,
+        name: &'a str,
+        machine: &'a str,
+        quantity: f32,
+        power: f32,
+    }
+
+    serializer.collect_map(recipes.iter().map(|(product, product_recipes)| {
+        (
+            product,
+            product_recipes
+                .iter()
+                .map(|((recipe, machine), usage)| RecipeTotal {
+                    recipe: recipe + 1,
+                    name: &usage.name,
+                    machine,
+                    quantity: usage.quantity,
+                    power: usage.power,
+                })
+                .collect::<Vec<_>>(),
+        )
+    }))
+}
+
+impl Display for DependencyResolutionTotals {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        DependencyResolutionTotalsDisplay {
+            totals: self,
+            show_perfect_splits: false,
+            show_recipes: false,
+        }
+        .fmt(f)
+    }
+}
+
+struct DependencyResolutionTotalsDisplay<'a> {
+    totals: &'a DependencyResolutionTotals,
+    show_perfect_splits: bool,
+    show_recipes: bool,
+}
+
+impl Display for DependencyResolutionTotalsDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_products(f)?;
+        self.fmt_machines(f)
+    }
+}
+
+impl DependencyResolutionTotalsDisplay<'_> {
+    /// Show the ingredients, products & byproducts of the factory, and the recipes used if asked for
+    fn fmt_products(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let unused_byproducts = self
+            .totals
+            .byproducts
+            .iter()
+            .filter_map(|(byproduct, quantity_produced)| {
+                let quantity_used = self.totals.byproduct_inputs.get(byproduct).unwrap_or(&0.0);
+                if quantity_used >= quantity_produced {
+                    None
+                } else {
+                    Some((byproduct.clone(), quantity_produced - quantity_used))
+                }
+            })
+            .collect();
+
+        for (heading, product_list) in [
+            ("Input Ingredients:", &self.totals.inputs),
+            (
+                "Intermediate Ingredients:",
+                &self.totals.intermediate_ingredients,
+            ),
+            ("Output Products:", &self.totals.outputs),
+            ("Byproducts:", &unused_byproducts),
+        ] {
+            if !product_list.is_empty() {
+                writeln!(f, "{heading}")?;
+                for (product, quantity) in product_list.iter() {
+                    writeln!(f, " * {:.2} {}", quantity, product)?;
+                }
+
+                writeln!(f)?;
+            }
+        }
+
+        if self.show_recipes && !self.totals.recipes.is_empty() {
+            writeln!(f, "Recipes:")?;
+            for (product, product_recipes) in self.totals.recipes.iter() {
+                writeln!(f, " * {}", product)?;
+                for ((recipe, machine), usage) in product_recipes.iter() {
+                    writeln!(
+                        f,
+                        "   - {:.2} from recipe {} ({}) in {} ({})",
+                        usage.quantity,
+                        recipe + 1,
+                        usage.name,
+                        machine,
+                        PowerDisplay(usage.power)
+                    )?;
+                }
+            }
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+
+    /// Show the machines & extractors in the factory, and the power they draw
+    fn fmt_machines(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Machines:")?;
+        for (machine, machine_products) in self.totals.machines.iter() {
+            writeln!(
+                f,
+                " * {} ({})",
+                machine,
+                PowerDisplay(self.totals.machine_power(machine))
+            )?;
+            for (product, quantity) in machine_products.iter() {
+                let power = PowerDisplay(self.totals.power[machine][product]);
+                let clock = ClockDisplay(self.totals.clocks[machine][product]);
+                // generators make power, which doesn't come in plurals
+                let plural = if power.0 < 0.0 { "" } else { "s" };
+                writeln!(
+                    f,
+                    "   - {:.2} for {}{}{} ({})",
+                    quantity, product, plural, clock, power
+                )?;
+                if self.show_perfect_splits {
+                    for split in perfect_splits(*quantity, clock.0) {
+                        writeln!(f, "     > {}", split)?;
+                    }
+                }
+            }
+        }
+
+        if !self.totals.extraction.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "Extraction:")?;
+            for (resource, plan) in self.totals.extraction.iter() {
+                writeln!(f, " * {} ({})", resource, PowerDisplay(plan.power()))?;
+                for extractor in plan.extractors.iter() {
+                    writeln!(
+                        f,
+                        "   - {} {}{}{}{} ({})",
+                        extractor.count,
+                        extractor.machine,
+                        if extractor.count == 1 { "" } else { "s" },
+                        extractor.purity.map_or_else(String::new, |purity| format!(
+                            " on {}{} node{}",
+                            match (extractor.count, purity) {
+                                (1, Purity::Impure) => "an ",
+                                (1, _) => "a ",
+                                _ => "",
+                            },
+                            purity,
+                            if extractor.count == 1 { "" } else { "s" }
+                        )),
+                        ClockDisplay(extractor.clock),
+                        PowerDisplay(extractor.power)
+                    )?;
+                }
+                if plan.shortfall > 0.0 {
+                    writeln!(f, "   - Not enough nodes, {:.2} short!", plan.shortfall)?;
+                }
+            }
+        }
+
+        writeln!(f)?;
+        writeln!(f, "Total Power: {:.2} MW", self.totals.total_power())?;
+        let power_generated = self.totals.power_generated();
+        if power_generated > 0.0 {
+            writeln!(f, "Power Generated: {:.2} MW", power_generated)?;
+            writeln!(
+                f,
+                "Net Power: {:.2} MW",
+                power_generated - self.totals.total_power()
+            )?;
+        }
+        let power_shards = self.totals.power_shards();
+        if power_shards > 0 {
+            writeln!(f, "Power Shards: {}", power_shards)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Shows a clock speed after a machine count, if it isn't the default
+struct ClockDisplay(f32);
+
+impl Display for ClockDisplay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if (self.0 - 100.0).abs() >= 0.005 {
+            write!(f, " at {:.2}%", self.0)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum BeltTier {
+    Mk1,
+    Mk2,
+    Mk3,
+    Mk4,
+    Mk5,
+    Mk6,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum PipeTier {
+    Mk1,
+    Mk2,
+}
+
+/// The best conveyor belts and pipelines available to carry items around the factory
+#[derive(Clone, Debug)]
+pub struct Logistics {
+    pub max_belt: BeltTier,
+    pub max_pipe: PipeTier,
+    /// items carried by pipes instead of belts
+    pub fluids: HashSet<String>,
+}
+
+impl Logistics {
+    fn speeds(&self, item: &str) -> &'static [f32] {
+        if self.fluids.contains(item) {
+            &PIPE_SPEEDS[..=self.max_pipe as usize]
+        } else {
+            &BELT_SPEEDS[..=self.max_belt as usize]
+        }
+    }
+
+    /// The most of an item that can be carried on one belt or pipe
+    fn limit(&self, item: &str) -> f32 {
+        *self.speeds(item).last().unwrap()
+    }
+
+    /// The belts or pipes needed to carry a quantity of an item; the slowest tier that can carry
+    /// it alone, or as many of the fastest tier as it takes
+    pub fn describe(&self, item: &str, quantity: f32) -> String {
+        let speeds = self.speeds(item);
+        let kind = if self.fluids.contains(item) {
+            "pipe"
+        } else {
+            "belt"
+        };
+        let (tier, count) = match speeds
+            .iter()
+            .position(|speed| quantity <= speed * (1.0 + FLOW_EPSILON as f32))
+        {
+            Some(tier) => (tier, 1),
+            None => (
+                speeds.len() - 1,
+                whole_machines(quantity / speeds[speeds.len() - 1]),
+            ),
+        };
+        format!(
+            "{} Mk{} {}{}",
+            count,
+            tier + 1,
+            kind,
+            if count == 1 { "" } else { "s" }
+        )
+    }
+}
+
+/// Items going in and out of a single machine of a production line by belt or pipe, per minute;
+/// generated power isn't carried by either, so it's left out
+fn machine_flows(product: &str, quantity: f32, source: &Source) -> Vec<(String, f32)> {
+    let Source::Recipe {
+        machine_quantity,
+        ingredients,
+        byproducts,
+        ..
+    } = source
+    else {
+        return Vec::new();
+    };
+    [(product.to_string(), quantity)]
+        .into_iter()
+        .chain(ingredients.iter().map(|ingredient| {
+            (
+                ingredient.name.clone(),
+                ingredient
+                    .sources
+                    .iter()
+                    .map(|(quantity, _)| quantity)
+                    .sum(),
+            )
+        }))
+        .chain(byproducts.iter().cloned())
+        .filter(|(item, _)| !item.ends_with(POWER_SUFFIX))
+        .map(|(item, flow)| (item, flow / machine_quantity))
+        .collect()
+}
+
+/// Shows power drawn, or power generated if negative
+struct PowerDisplay(f32);
+
+impl Display for PowerDisplay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.0 < 0.0 {
+            write!(f, "{:.2} MW generated", -self.0)
+        } else {
+            write!(f, "{:.2} MW", self.0)
+        }
+    }
+}
+
+/// Machines draw power proportional to their clock speed raised to this exponent
+const OVERCLOCK_POWER_EXPONENT: f32 = 1.321_928;
+const MAX_CLOCK_SPEED: f32 = 250.0;
+
+/// Clock speeds for each production line, as percentages
+#[derive(Debug, Clone)]
+pub struct ClockSpeeds {
+    pub default: f32,
+    pub products: HashMap<String, f32>,
+}
+
+impl Default for ClockSpeeds {
+    fn default() -> Self {
+        ClockSpeeds {
+            default: 100.0,
+            products: HashMap::new(),
+        }
+    }
+}
+
+impl ClockSpeeds {
+    fn get(&self, product: &String) -> f32 {
+        *self.products.get(product).unwrap_or(&self.default)
+    }
+}
+
+/// The number of power shards a single machine needs to run at `clock` percent
+fn power_shards(clock: f32) -> u32 {
+    ((clock - 100.0) / 50.0 - FLOW_EPSILON as f32)
+        .ceil()
+        .max(0.0) as u32
+}
+
+/// The number of power shards a production line of `machine_quantity` machines at `clock` percent
+/// needs, when the fraction of a machine left over is run by one more machine at a lower clock
+fn line_power_shards(machine_quantity: f32, clock: f32) -> u32 {
+    let full_machines = (machine_quantity + FLOW_EPSILON as f32).floor();
+    let leftover = (machine_quantity - full_machines).max(0.0);
+    full_machines as u32 * power_shards(clock) + power_shards(leftover * clock)
+}
+
+/// The number of machines that must be built to run `machine_quantity` machines' worth of production
+fn whole_machines(machine_quantity: f32) -> u32 {
+    (machine_quantity - FLOW_EPSILON as f32).ceil().max(0.0) as u32
+}
+
+/// Rescale every production line in the tree to run at its configured clock speed, slowed down
+/// further if needed so that no machine takes in or puts out more than one belt or pipe can carry
+fn apply_clock_speeds(
+    product: &mut Product,
+    clock_speeds: &ClockSpeeds,
+    logistics: Option<&Logistics>,
+) {
+    for (quantity, source) in product.sources.iter_mut() {
+        let mut target_clock = clock_speeds.get(&product.name);
+        if let (Some(logistics), Source::Recipe { clock, .. }) = (logistics, &source) {
+            for (item, flow) in machine_flows(&product.name, *quantity, source) {
+                target_clock = target_clock.min(clock * logistics.limit(&item) / flow);
+            }
+        }
+        if let Source::Recipe {
+            machine_quantity,
+            clock,
+            power,
+            ingredients,
+            ..
+        } = source
+        {
+            let adjustment = target_clock / *clock;
+            *machine_quantity /= adjustment;
+            // generator output scales linearly with clock speed, so only consumers change in total
+            if *power > 0.0 {
+                *power *= adjustment.powf(OVERCLOCK_POWER_EXPONENT - 1.0);
+            }
+            *clock = target_clock;
+            for ingredient in ingredients.iter_mut() {
+                apply_clock_speeds(ingredient, clock_speeds, logistics);
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum Purity {
+    Impure,
+    Normal,
+    Pure,
+}
+
+impl Purity {
+    fn multiplier(&self) -> f32 {
+        match self {
+            Purity::Impure => 0.5,
+            Purity::Normal => 1.0,
+            Purity::Pure => 2.0,
+        }
+    }
+}
+
+impl Display for Purity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Purity::Impure => write!(f, "impure"),
+            Purity::Normal => write!(f, "normal"),
+            Purity::Pure => write!(f, "pure"),
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, Default)]
+pub enum MinerTier {
+    #[default]
+    Mk1,
+    Mk2,
+    Mk3,
+}
+
+struct Extractor {
+    name: &'static str,
+    /// Power drawn per extractor in MW
+    power: f32,
+    /// Resources extracted per minute on a normal node at 100%
+    rate: f32,
+}
+
+const MINERS: [Extractor; 3] = [
+    Extractor {
+        name: "Miner Mk1",
+        power: 5.0,
+        rate: 60.0,
+    },
+    Extractor {
+        name: "Miner Mk2",
+        power: 12.0,
+        rate: 120.0,
+    },
+    Extractor {
+        name: "Miner Mk3",
+        power: 30.0,
+        rate: 240.0,
+    },
+];
+const OIL_EXTRACTOR: Extractor = Extractor {
+    name: "Oil Extractor",
+    power: 40.0,
+    rate: 120.0,
+};
+const WATER_EXTRACTOR: Extractor = Extractor {
+    name: "Water Extractor",
+    power: 20.0,
+    rate: 120.0,
+};
+/// Resource well extractors draw no power of their own; the pressurizer powers the whole well
+const RESOURCE_WELL_EXTRACTOR: Extractor = Extractor {
+    name: "Resource Well Extractor",
+    power: 0.0,
+    rate: 60.0,
+};
+const RESOURCE_WELL_PRESSURIZER: Extractor = Extractor {
+    name: "Resource Well Pressurizer",
+    power: 150.0,
+    rate: 0.0,
+};
+
+/// The resource nodes available for a single resource
+#[derive(Debug, Clone, Default)]
+pub struct ResourceNodes {
+    pub nodes: BTreeMap<Purity, u32>,
+    /// Number of resource wells the nodes are spread across, if they are resource well nodes
+    pub wells: Option<u32>,
+}
+
+impl ResourceNodes {
+    fn merge(&mut self, other: &ResourceNodes) {
+        for (purity, count) in &other.nodes {
+            *self.nodes.get_default(purity) += count;
+        }
+        if let Some(wells) = other.wells {
+            *self.wells.get_or_insert(0) += wells;
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ExtractorAllocation {
+    pub machine: String,
+    pub purity: Option<Purity>,
+    pub count: u32,
+    pub clock: f32,
+    /// Total power drawn by all of these extractors in MW
+    pub power: f32,
+}
+
+impl ExtractorAllocation {
+    fn new(extractor: &Extractor, purity: Option<Purity>, count: u32, clock: f32) -> Self {
+        ExtractorAllocation {
+            machine: extractor.name.to_string(),
+            purity,
+            count,
+            clock,
+            power: count as f32 * extractor.power * (clock / 100.0).powf(OVERCLOCK_POWER_EXPONENT),
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ExtractionPlan {
+    pub extractors: Vec<ExtractorAllocation>,
+    /// Resources that couldn't be extracted from the available nodes, even at the maximum clock speed
+    pub shortfall: f32,
+}
+
+impl ExtractionPlan {
+    fn power(&self) -> f32 {
+        self.extractors
+            .iter()
+            .map(|extractor| extractor.power)
+            .sum()
+    }
+}
+
+/// Plan extractors for a single raw resource. Available nodes are filled from the purest down, with
+/// the last node underclocked. If there aren't enough nodes, every extractor is overclocked to make up
+/// the difference. Without any listed nodes, normal nodes are assumed, as many as are needed.
+fn plan_resource_extraction(
+    resource: &str,
+    demand: f32,
+    nodes: Option<&ResourceNodes>,
+    miner: MinerTier,
+) -> Option<ExtractionPlan> {
+    let extractor = match resource {
+        "Water" if nodes.is_some() => &RESOURCE_WELL_EXTRACTOR,
+        "Water" => &WATER_EXTRACTOR,
+        "Crude Oil" if nodes.is_some_and(|nodes| nodes.wells.is_some()) => &RESOURCE_WELL_EXTRACTOR,
+        "Crude Oil" => &OIL_EXTRACTOR,
+        "Nitrogen Gas" => &RESOURCE_WELL_EXTRACTOR,
+        _ if RESOURCE_WEIGHTS.iter().any(|(name, _)| *name == resource) => &MINERS[miner as usize],
+        _ => return None,
+    };
+    let well = extractor.name == RESOURCE_WELL_EXTRACTOR.name;
+
+    // water extractors can be placed anywhere
+    let Some(nodes) = nodes.filter(|_| extractor.name != WATER_EXTRACTOR.name) else {
+        let count = whole_machines(demand / extractor.rate).max(1);
+        let clock = demand / (count as f32 * extractor.rate) * 100.0;
+        let purity = (extractor.name != WATER_EXTRACTOR.name).then_some(Purity::Normal);
+        let mut extractors = vec![ExtractorAllocation::new(extractor, purity, count, clock)];
+        if well {
+            extractors.insert(
+                0,
+                ExtractorAllocation::new(&RESOURCE_WELL_PRESSURIZER, None, 1, clock),
+            );
+        }
+        return Some(ExtractionPlan {
+            extractors,
+            shortfall: 0.0,
+        });
+    };
+
+    let capacity: f32 = nodes
+        .nodes
+        .iter()
+        .map(|(purity, count)| *count as f32 * extractor.rate * purity.multiplier())
+        .sum();
+    let mut extractors = Vec::new();
+    let mut shortfall = 0.0;
+    if capacity < demand || well {
+        // every node runs at the same clock speed; resource well extractors can't be clocked
+        // individually, since they run at the speed of their pressurizer
+        let clock = demand / capacity * 100.0;
+        if clock > MAX_CLOCK_SPEED {
+            shortfall = demand - capacity * MAX_CLOCK_SPEED / 100.0;
+        }
+        let clock = clock.min(MAX_CLOCK_SPEED);
+        if well {
+            extractors.push(ExtractorAllocation::new(
+                &RESOURCE_WELL_PRESSURIZER,
+                None,
+                nodes.wells.unwrap_or(1),
+                clock,
+            ));
+        }
+        for (purity, count) in nodes.nodes.iter().rev() {
+            extractors.push(ExtractorAllocation::new(
+                extractor,
+                Some(*purity),
+                *count,
+                clock,
+            ));
+        }
+    } else {
+        let mut remaining = demand;
+        for (purity, count) in nodes.nodes.iter().rev() {
+            if remaining <= FLOW_EPSILON as f32 {
+                break;
+            }
+            let node_rate = extractor.rate * purity.multiplier();
+            // round off to the nearest hundredth of a percent, to not leave behind tiny rounding errors
+            let full = (((remaining / node_rate * 1e4).round() / 1e4) as u32).min(*count);
+            if full > 0 {
+                extractors.push(ExtractorAllocation::new(
+                    extractor,
+                    Some(*purity),
+                    full,
+                    100.0,
+                ));
+                remaining -= full as f32 * node_rate;
+            }
+            if full < *count && remaining / node_rate >= 1e-4 {
+                let clock = remaining / node_rate * 100.0;
+                extractors.push(ExtractorAllocation::new(extractor, Some(*purity), 1, clock));
+                remaining = 0.0;
+            }
+        }
+    }
+    Some(ExtractionPlan {
+        extractors,
+        shortfall,
+    })
+}
+
+/// One way of evenly dividing a belt between machines
+#[derive(Debug, Clone)]
+struct PerfectSplit {
+    name: &'static str,
+    /// machine groups fed by the split, as machine counts and the clock speed they run at
+    groups: Vec<(u32, f32)>,
+    /// splitters & mergers needed to build the split
+    topology: String,
+}
+
+impl Display for PerfectSplit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: ", self.name)?;
+        for (i, (machines, clock)) in self.groups.iter().enumerate() {
+            if i > 0 {
+                write!(f, " + ")?;
+            }
+            write!(f, "{} at {:.2}%", machines, clock)?;
+        }
+        write!(f, " using {}", self.topology)
+    }
+}
+
+/// The smallest number of outputs at least `count` that a tree of 2- and 3-way splitters can make,
+/// as the splitter sizes of each layer of the tree. Smaller splitters go first, since every later
+/// layer needs one splitter per output of the layer before it
+fn splitter_tree(count: u32) -> Vec<u32> {
+    let count = count.max(1) as u64;
+    let mut best: Option<(u64, u32, u32)> = None;
+    let mut pow3 = 1;
+    for threes in 0.. {
+        let mut twos = 0;
+        while pow3 << twos < count {
+            twos += 1;
+        }
+        let outputs = pow3 << twos;
+        if best.is_none_or(|(best_outputs, _, _)| outputs < best_outputs) {
+            best = Some((outputs, twos, threes));
+        }
+        if pow3 >= count {
+            break;
+        }
+        pow3 *= 3;
+    }
+    let (_, twos, threes) = best.unwrap();
+    [2].repeat(twos as usize)
+        .into_iter()
+        .chain([3].repeat(threes as usize))
+        .collect()
+}
+
+fn describe_splitter_tree(layers: &[u32]) -> String {
+    if layers.is_empty() {
+        return "no splitters".to_string();
+    }
+    layers
+        .iter()
+        .scan(1, |splitters, layer| {
+            let layer_splitters = *splitters;
+            *splitters *= layer;
+            Some(format!(
+                "{} {}-way splitter{}",
+                layer_splitters,
+                layer,
+                if layer_splitters == 1 { "" } else { "s" }
+            ))
+        })
+        .collect::<Vec<_>>()
+        .join(" -> ")
+}
+
+/// Ways to evenly divide a belt between enough machines to run `machine_quantity` machines at `clock`
+/// percent, with every machine underclocked to the same speed within its group. Empty if no machines
+/// are needed
+fn perfect_splits(machine_quantity: f32, clock: f32) -> Vec<PerfectSplit> {
+    // total work to be done, in percent of a machine
+    let work = machine_quantity * clock;
+    let machines = whole_machines(machine_quantity);
+    if machines == 0 {
+        return Vec::new();
+    }
+    let mut splits = Vec::new();
+
+    // a plain splitter tree, with a machine on every output
+    let tree = splitter_tree(machines);
+    let outputs = tree.iter().product::<u32>();
+    splits.push(PerfectSplit {
+        name: "splitter tree",
+        groups: vec![(outputs, work / outputs as f32)],
+        topology: describe_splitter_tree(&tree),
+    });
+
+    if outputs > machines {
+        // the same tree, with the outputs that have no machine merged back into its input
+        let unused = outputs - machines;
+        // every merger joins up to 3 belts into 1, including the input belt
+        let mergers = unused.div_ceil(2);
+        splits.push(PerfectSplit {
+            name: "splitter tree with loopback",
+            groups: vec![(machines, work / machines as f32)],
+            topology: format!(
+                "{}, with {} output{} merged back into the input by {} merger{}",
+                describe_splitter_tree(&tree),
+                unused,
+                if unused == 1 { "" } else { "s" },
+                mergers,
+                if mergers == 1 { "" } else { "s" }
+            ),
+        });
+    }
+
+    if machines > 1 {
+        // a row of splitters; the machines only share evenly once the row backs up
+        splits.push(PerfectSplit {
+            name: "manifold",
+            groups: vec![(machines, work / machines as f32)],
+            topology: format!(
+                "{} splitter{} in a row, balancing once the belt is saturated",
+                machines - 1,
+                if machines == 2 { "" } else { "s" }
+            ),
+        });
+    }
+
+    // split the belt in half, or into a third & two thirds, and feed a splitter tree from each part
+    let mut best_groups: Option<PerfectSplit> = None;
+    for (part, parts) in [(1, 2), (1, 3)] {
+        let shares = [
+            part as f32 / parts as f32,
+            (parts - part) as f32 / parts as f32,
+        ];
+        let trees = shares.map(|share| splitter_tree(whole_machines(machine_quantity * share)));
+        let group_machines = trees.clone().map(|tree| tree.iter().product::<u32>());
+        let total_machines: u32 = group_machines.iter().sum();
+        if total_machines >= outputs
+            || best_groups.as_ref().is_some_and(|best| {
+                total_machines >= best.groups.iter().map(|(machines, _)| machines).sum()
+            })
+        {
+            continue;
+        }
+        best_groups = Some(PerfectSplit {
+            name: "two groups",
+            groups: (0..2)
+                .map(|i| {
+                    (
+                        group_machines[i],
+                        work * shares[i] / group_machines[i] as f32,
+                    )
+                })
+                .collect(),
+            topology: format!(
+                "1 {}-way splitter{}, then {} for the first group and {} for the second",
+                parts,
+                if parts == 3 {
+                    " with 2 outputs merged by 1 merger"
+                } else {
+                    ""
+                },
+                describe_splitter_tree(&trees[0]),
+                describe_splitter_tree(&trees[1]),
+            ),
+        });
+    }
+    splits.extend(best_groups);
+
+    splits
+}
+
+/// Flow rates below this threshold are treated as zero when reading back a solution
+const FLOW_EPSILON: f64 = 1e-6;
+
+/// A flow-balance model of a factory: every recipe in use is a variable, and every item
+/// that passes through the factory is a conservation constraint
+#[derive(Debug)]
+struct ProductionModel {
+    /// every item that participates in the factory, in the order they were discovered
+    items: Vec<String>,
+    /// the recipes that may be used to produce each item crafted within the factory, keyed by
+    /// the item and the recipe's index
+    recipes: BTreeMap<(String, usize), Recipe>,
+    /// items provided by the user, along with the rate they are available at, if limited
+    provided: BTreeMap<String, Option<f32>>,
+    /// items that may be drawn in unlimited quantities from outside the factory, along with the
+    /// cost of drawing each one
+    raw: BTreeMap<String, f64>,
+    /// power drawn per machine in MW, for every machine with known power usage
+    machine_power: HashMap<String, f32>,
+    reuse_byproducts: bool,
+}
+
+/// Objective weights and bounds for a single pass over a `ProductionModel`
+struct SolverStage {
+    direction: OptimizationDirection,
+    /// objective weight & bounds for the scale applied to products with a requested rate
+    requested_scale: (f64, (f64, f64)),
+    /// objective weight & bounds for the scale applied to products without a requested rate
+    unrequested_scale: (f64, (f64, f64)),
+    /// objective weight per unit of raw resource drawn into the factory
+    raw_cost: f64,
+    /// objective weight per machine and per unit of unused byproduct, used to break ties
+    /// between otherwise equivalent factories
+    tie_break_cost: f64,
+}
+
+/// Variable values read back from a solved `ProductionModel`
+#[derive(Debug, Default)]
+struct ModelSolution {
+    requested_scale: f64,
+    unrequested_scale: f64,
+    machines: HashMap<(String, usize), f64>,
+    supplied: HashMap<String, f64>,
+    excess: HashMap<String, f64>,
+}
+
+impl ProductionModel {
+    fn new(
+        recipes: &IndexedMap<String, Recipe>,
+        machines: &HashMap<String, Machine>,
+        products: &[(String, Option<f32>)],
+        ingredients: &HashMap<String, Option<f32>>,
+        resupply_insufficient: bool,
+        reuse_byproducts: bool,
+        optimize: bool,
+    ) -> ProductionModel {
+        let mut model = ProductionModel {
+            items: Vec::new(),
+            recipes: BTreeMap::new(),
+            provided: BTreeMap::new(),
+            raw: BTreeMap::new(),
+            machine_power: machines
+                .iter()
+                .map(|(name, machine)| (name.clone(), machine.power))
+                .collect(),
+            reuse_byproducts,
+        };
+
+        let mut seen = HashSet::new();
+        let mut queue = products
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect::<VecDeque<_>>();
+        while let Some(item) = queue.pop_front() {
+            if !seen.insert(item.clone()) {
+                continue;
+            }
+            model.items.push(item.clone());
+
+            // determine where this item may be sourced from
+            let available = ingredients.get(&item);
+            if let Some(limit) = available {
+                model.provided.insert(item.clone(), *limit);
+            }
+            let craftable = match available {
+                Some(Some(_)) => resupply_insufficient,
+                Some(None) => false,
+                None => true,
+            };
+            if !craftable {
+                continue;
+            }
+
+            // consider every alternate recipe when optimizing, unless one was picked explicitly
+            let candidates = match recipes.get_index(&item) {
+                _ if BASIC_INGREDIENTS.contains(&item.as_str()) => Vec::new(),
+                None => Vec::new(),
+                Some(_) if optimize && !recipes.index.contains_key(&item) => {
+                    recipes.map[&item].iter().cloned().enumerate().collect()
+                }
+                Some(index) => vec![(index, recipes.map[&item][index].clone())],
+            };
+            if candidates.is_empty() {
+                let cost = RESOURCE_WEIGHTS
+                    .iter()
+                    .find(|(resource, _)| *resource == item)
+                    .map_or(1.0, |(_, weight)| *weight);
+                model.raw.insert(item, cost);
+                continue;
+            }
+            for (index, recipe) in candidates {
+                queue.extend(
+                    recipe
+                        .ingredients
+                        .iter()
+                        .map(|(ingredient, _)| ingredient.clone()),
+                );
+                if reuse_byproducts {
+                    queue.extend(recipe.products.iter().map(|(product, _)| product.clone()));
+                }
+                model.recipes.insert((item.clone(), index), recipe);
+            }
+        }
+
+        model
+    }
+
+    /// Find a loop of recipes that prevents `product` from being made, if there is one. A product
+    /// can't be made when every recipe for it depends, directly or indirectly, on itself, without
+    /// any outside input to get the loop started
+    fn find_closed_cycle(&self, product: &String) -> Option<Vec<String>> {
+        let mut producible = self
+            .provided
+            .keys()
+            .chain(self.raw.keys())
+            .collect::<HashSet<_>>();
+        loop {
+            let newly_producible = self
+                .recipes
+                .iter()
+                .filter(|((item, _), recipe)| {
+                    !producible.contains(item)
+                        && recipe
+                            .ingredients
+                            .iter()
+                            .all(|(ingredient, _)| producible.contains(ingredient))
+                })
+                .map(|((item, _), _)| item)
+                .collect::<Vec<_>>();
+            if newly_producible.is_empty() {
+                break;
+            }
+            producible.extend(newly_producible);
+        }
+
+        // follow unproducible ingredients down from the product until one repeats
+        let mut path = vec![product.clone()];
+        let mut current = product;
+        while !producible.contains(current) {
+            current = self
+                .recipes
+                .iter()
+                .find(|((item, _), _)| item == current)
+                .and_then(|(_, recipe)| {
+                    recipe
+                        .ingredients
+                        .iter()
+                        .find(|(ingredient, _)| !producible.contains(ingredient))
+                })
+                .map(|(ingredient, _)| ingredient)?;
+            if let Some(start) = path.iter().position(|item| item == current) {
+                path.push(current.clone());
+                return Some(path.split_off(start));
+            }
+            path.push(current.clone());
+        }
+        None
+    }
+
+    /// The rate a single machine produces `product` at, used as the base rate for products without a requested rate
+    fn base_rate(recipes: &IndexedMap<String, Recipe>, product: &String) -> f64 {
+        recipes
+            .get(product)
+            .and_then(|recipe| {
+                recipe
+                    .products
+                    .iter()
+                    .find(|(recipe_product, _)| recipe_product == product)
+                    .map(|(_, quantity)| *quantity as f64)
+            })
+            .unwrap_or(1.0)
+    }
+
+    fn solve_stage(
+        &self,
+        demands: &[(String, f64, bool)],
+        stage: &SolverStage,
+    ) -> Result<ModelSolution, minilp::Error> {
+        let mut problem = Problem::new(stage.direction);
+        let unbounded = (0.0, f64::INFINITY);
+
+        let requested = problem.add_var(stage.requested_scale.0, stage.requested_scale.1);
+        let unrequested = problem.add_var(stage.unrequested_scale.0, stage.unrequested_scale.1);
+        let machines = self
+            .recipes
+            .keys()
+            .map(|item| (item, problem.add_var(stage.tie_break_cost, unbounded)))
+            .collect::<BTreeMap<_, _>>();
+        let provided = self
+            .provided
+            .iter()
+            .map(|(item, limit)| {
+                let max = limit.map_or(f64::INFINITY, |limit| limit as f64);
+                (item, problem.add_var(0.0, (0.0, max)))
+            })
+            .collect::<BTreeMap<_, _>>();
+        let raw = self
+            .raw
+            .iter()
+            .map(|(item, cost)| (item, problem.add_var(stage.raw_cost * cost, unbounded)))
+            .collect::<BTreeMap<_, _>>();
+        let excess = self
+            .items
+            .iter()
+            .map(|item| (item, problem.add_var(stage.tie_break_cost, unbounded)))
+            .collect::<BTreeMap<_, _>>();
+
+        // collect the terms of each item's conservation constraint
+        let mut balances: BTreeMap<&String, BTreeMap<Variable, f64>> = BTreeMap::new();
+        for (key @ (item, _), recipe) in &self.recipes {
+            let machine = machines[key];
+            for (product, quantity) in &recipe.products {
+                if product == item || self.reuse_byproducts {
+                    *balances.get_default(&product).get_default(&machine) += *quantity as f64;
+                }
+            }
+            for (ingredient, quantity) in &recipe.ingredients {
+                *balances.get_default(&ingredient).get_default(&machine) -= *quantity as f64;
+            }
+        }
+        for (item, variable) in provided.iter().chain(raw.iter()) {
+            *balances.get_default(item).get_default(variable) += 1.0;
+        }
+        for (item, variable) in &excess {
+            *balances.get_default(item).get_default(variable) -= 1.0;
+        }
+        for (product, rate, is_requested) in demands {
+            let scale = if *is_requested {
+                requested
+            } else {
+                unrequested
+            };
+            *balances.get_default(&product).get_default(&scale) -= rate;
+        }
+        for (_, terms) in balances {
+            problem.add_constraint(terms, ComparisonOp::Eq, 0.0);
+        }
+
+        // only byproducts may go unused, so that every recipe in use is needed by the products it is used for
+        for (item, variable) in &excess {
+            let mut terms = BTreeMap::from([(*variable, 1.0)]);
+            if self.reuse_byproducts {
+                for (key, recipe) in self
+                    .recipes
+                    .iter()
+                    .filter(|((producer, _), _)| producer != *item)
+                {
+                    *terms.get_default(&machines[key]) -= output_rate(recipe, item);
+                }
+            }
+            problem.add_constraint(terms, ComparisonOp::Le, 0.0);
+        }
+
+        let solution = problem.solve()?;
+        let mut supplied = read_solution(&solution, &provided);
+        for (item, quantity) in read_solution(&solution, &raw) {
+            *supplied.get_default(&item) += quantity;
+        }
+        Ok(ModelSolution {
+            requested_scale: solution[requested],
+            unrequested_scale: solution[unrequested],
+            machines: read_solution(&solution, &machines),
+            supplied,
+            excess: read_solution(&solution, &excess),
+        })
+    }
+
+    /// Solve the model in three passes: first maximize the output of products with requested rates,
+    /// then maximize the output of products without requested rates, and finally minimize the raw
+    /// resources needed to produce those outputs
+    fn solve(&self, demands: &[(String, f64, bool)]) -> Result<ModelSolution, Error> {
+        let any_requested = demands.iter().any(|(_, _, is_requested)| *is_requested);
+        let any_unrequested = demands.iter().any(|(_, _, is_requested)| !*is_requested);
+        let fixed = |value: f64| (0.0, (value, value));
+
+        let requested_scale = if any_requested {
+            self.solve_stage(
+                demands,
+                &SolverStage {
+                    direction: OptimizationDirection::Maximize,
+                    requested_scale: (1.0, (0.0, 1.0)),
+                    unrequested_scale: fixed(0.0),
+                    raw_cost: 0.0,
+                    tie_break_cost: 0.0,
+                },
+            )?
+            .requested_scale
+        } else {
+            0.0
+        };
+
+        let unrequested_scale = if any_unrequested {
+            match self.solve_stage(
+                demands,
+                &SolverStage {
+                    direction: OptimizationDirection::Maximize,
+                    requested_scale: fixed(requested_scale),
+                    unrequested_scale: (1.0, (0.0, f64::INFINITY)),
+                    raw_cost: 0.0,
+                    tie_break_cost: 0.0,
+                },
+            ) {
+                Ok(solution) if solution.unrequested_scale.is_finite() => {
+                    solution.unrequested_scale
+                }
+                // nothing limits production, so default to a single machine's worth
+                Ok(_) | Err(minilp::Error::Unbounded) => 1.0,
+                Err(err) => return Err(err.into()),
+            }
+        } else {
+            0.0
+        };
+
+        self.solve_stage(
+            demands,
+            &SolverStage {
+                direction: OptimizationDirection::Minimize,
+                requested_scale: fixed(requested_scale),
+                unrequested_scale: fixed(unrequested_scale),
+                raw_cost: 1.0,
+                tie_break_cost: 1e-3,
+            },
+        )
+        .map_err(Error::from)
+    }
+
+    /// Convert a solved model back into a tree that draws `demand` of `name` from the solved flows.
+    /// `path` holds the items between the root of the tree and this node, and is used to cut
+    /// recipe loops short
+    fn expand(
+        &self,
+        solution: &ModelSolution,
+        name: &String,
+        demand: f64,
+        path: &mut Vec<String>,
+    ) -> Result<Product, Error> {
+        if path.contains(name) {
+            return Ok(Product {
+                name: name.clone(),
+                sources: vec![(demand as f32, Source::Cycle)],
+            });
+        }
+
+        path.push(name.clone());
+        let mut product = self.expand_sources(solution, name, demand, path)?;
+
+        // if some of this product is fed back into itself, the loop must produce enough
+        // to cover both the demand and the amount that is recycled
+        let recycled = product.recycled(name) as f64;
+        if recycled > FLOW_EPSILON {
+            let loop_gain = recycled / demand;
+            if loop_gain >= 1.0 - FLOW_EPSILON {
+                return Err(Error::DivergentLoop(name.clone()));
+            }
+            product = self.expand_sources(solution, name, demand / (1.0 - loop_gain), path)?;
+        }
+        path.pop();
+
+        Ok(product)
+    }
+
+    fn expand_sources(
+        &self,
+        solution: &ModelSolution,
+        name: &String,
+        demand: f64,
+        path: &mut Vec<String>,
+    ) -> Result<Product, Error> {
+        let mut product = Product {
+            name: name.clone(),
+            sources: Vec::new(),
+        };
+
+        let supplied = *solution.supplied.get(name).unwrap_or(&0.0);
+        let mut production = Vec::new();
+        let mut byproduct_inflow = 0.0;
+        for (key @ (item, recipe_index), recipe) in &self.recipes {
+            let machines = *solution.machines.get(key).unwrap_or(&0.0);
+            if item == name {
+                production.push((*recipe_index, recipe, machines));
+            } else if self.reuse_byproducts {
+                byproduct_inflow += machines * output_rate(recipe, name);
+            }
+        }
+        let produced = production
+            .iter()
+            .map(|(_, recipe, machines)| machines * output_rate(recipe, name))
+            .sum::<f64>();
+
+        // unused flow is drawn from byproducts first, as they are the only source of surplus
+        let byproducts_used =
+            (byproduct_inflow - solution.excess.get(name).unwrap_or(&0.0)).max(0.0);
+        let total = supplied + produced + byproducts_used;
+        if total <= FLOW_EPSILON {
+            if demand > FLOW_EPSILON {
+                product.sources.push((demand as f32, Source::Supply));
+            }
+            return Ok(product);
+        }
+        let share = demand / total;
+
+        if supplied * share > FLOW_EPSILON {
+            product
+                .sources
+                .push(((supplied * share) as f32, Source::Supply));
+        }
+        if byproducts_used * share > FLOW_EPSILON {
+            product
+                .sources
+                .push(((byproducts_used * share) as f32, Source::Byproduct));
+        }
+        for (recipe_index, recipe, machines) in production {
+            let machine_quantity = machines * share;
+            let quantity = machine_quantity * output_rate(recipe, name);
+            if quantity <= FLOW_EPSILON {
+                continue;
+            }
+            let power = recipe
+                .power
+                .or_else(|| self.machine_power.get(&recipe.machine).copied())
+                .unwrap_or(0.0);
+            product.sources.push((
+                quantity as f32,
+                Source::Recipe {
+                    machine: recipe.machine.clone(),
+                    recipe: recipe_index,
+                    machine_quantity: machine_quantity as f32,
+                    clock: 100.0,
+                    power: machine_quantity as f32 * power,
+                    byproducts: recipe
+                        .products
+                        .iter()
+                        .filter(|(recipe_product, _)| recipe_product != name)
+                        .map(|(recipe_product, quantity)| {
+                            (
+                                recipe_product.clone(),
+                                (*quantity as f64 * machine_quantity) as f32,
+                            )
+                        })
+                        .collect(),
+                    ingredients: recipe
+                        .ingredients
+                        .iter()
+                        .map(|(ingredient, quantity)| {
+                            self.expand(
+                                solution,
+                                ingredient,
+                                *quantity as f64 * machine_quantity,
+                                path,
+                            )
+                        })
+                        .collect::<Result<_, _>>()?,
+                },
+            ));
+        }
+
+        Ok(product)
+    }
+}
+
+/// Read back the values of every non-zero variable in a solution
+fn read_solution<K: Clone + Eq + std::hash::Hash>(
+    solution: &Solution,
+    variables: &BTreeMap<&K, Variable>,
+) -> HashMap<K, f64> {
+    variables
+        .iter()
+        .map(|(key, variable)| ((*key).clone(), solution[*variable]))
+        .filter(|(_, value)| *value > FLOW_EPSILON)
+        .collect()
+}
+
+/// The rate a single machine running `recipe` outputs `product` at
+fn output_rate(recipe: &Recipe, product: &String) -> f64 {
+    recipe
+        .products
+        .iter()
+        .filter(|(recipe_product, _)| recipe_product == product)
+        .map(|(_, quantity)| *quantity as f64)
+        .sum()
+}
+
+/// A product's recipe that was passed over, as it forms a loop that can never get started
+#[derive(Serialize, Debug, Clone)]
+pub struct RecipeFallback {
+    pub product: String,
+    /// zero-based index of the recipe passed over, written out as its number from `--list-recipes`
+    #[serde(serialize_with = "serialize_recipe_number")]
+    pub recipe: usize,
+    pub recipe_name: String,
+    /// zero-based index of the recipe used instead
+    #[serde(serialize_with = "serialize_recipe_number")]
+    pub replacement: usize,
+    pub replacement_name: String,
+    /// The items in the loop, starting & ending with the same item
+    pub cycle: Vec<String>,
+}
+
+impl Display for RecipeFallback {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} recipe {} ({}) forms a loop with no outside input: {}; using recipe {} ({}) instead",
+            self.product,
+            self.recipe + 1,
+            self.recipe_name,
+            self.cycle.join(" -> "),
+            self.replacement + 1,
+            self.replacement_name
+        )
+    }
+}
+
+fn resolve_dependency_trees(
+    recipes: &IndexedMap<String, Recipe>,
+    machines: &HashMap<String, Machine>,
+    products: Vec<(String, Option<f32>)>,
+    ingredients: Vec<(String, Option<f32>)>,
+    resupply_insufficient: bool,
+    reuse_byproducts: bool,
+    optimize: bool,
+) -> Result<
+    (
+        Vec<Product>,
+        DependencyResolutionTotals,
+        Vec<RecipeFallback>,
+    ),
+    Error,
+> {
+    let ingredients = ingredients.into_iter().collect::<HashMap<_, _>>();
+
+    // when a product's default recipe leads into a loop that can never get started, fall back
+    // to its next recipe, unless the recipe was chosen explicitly. Whether a loop can run is left
+    // to the solver: a loop that can't shows up as nothing being made
+    let mut recipes = Cow::Borrowed(recipes);
+    let mut fallbacks = Vec::new();
+    let (model, demands, solution) = loop {
+        let model = ProductionModel::new(
+            &recipes,
+            machines,
+            &products,
+            &ingredients,
+            resupply_insufficient,
+            reuse_byproducts,
+            optimize,
+        );
+
+        // products with requested rates are resolved before products without them
+        let demands = products
+            .iter()
+            .filter(|(_, maybe_quantity)| maybe_quantity.is_some())
+            .chain(
+                products
+                    .iter()
+                    .filter(|(_, maybe_quantity)| maybe_quantity.is_none()),
+            )
+            .map(|(name, maybe_quantity)| match maybe_quantity {
+                Some(quantity) => (name.clone(), *quantity as f64, true),
+                None => (
+                    name.clone(),
+                    ProductionModel::base_rate(&recipes, name),
+                    false,
+                ),
+            })
+            .collect::<Vec<_>>();
+
+        let solution = model.solve(&demands);
+        let stalled = match &solution {
+            Ok(solution) => demands.iter().any(|(_, rate, is_requested)| {
+                let scale = if *is_requested {
+                    solution.requested_scale
+                } else {
+                    solution.unrequested_scale
+                };
+                *rate > 0.0 && scale <= FLOW_EPSILON
+            }),
+            Err(_) => true,
+        };
+        let closed = products
+            .iter()
+            .filter(|_| stalled)
+            .find_map(|(product, _)| {
+                model
+                    .find_closed_cycle(product)
+                    .map(|cycle| (product, cycle))
+            });
+        let Some((product, cycle)) = closed else {
+            break (model, demands, solution?);
+        };
+
+        let fallback = cycle.iter().find_map(|item| {
+            recipes
+                .get_index(item)
+                .filter(|index| {
+                    !optimize
+                        && !recipes.index.contains_key(item)
+                        && index + 1 < recipes.map[item].len()
+                })
+                .map(|index| (item.clone(), index))
+        });
+        match fallback {
+            Some((item, index)) => {
+                let recipe_name = |index: usize| {
+                    recipes.map[&item][index]
+                        .name
+                        .clone()
+                        .unwrap_or_else(|| item.clone())
+                };
+                fallbacks.push(RecipeFallback {
+                    product: item.clone(),
+                    recipe: index,
+                    recipe_name: recipe_name(index),
+                    replacement: index + 1,
+                    replacement_name: recipe_name(index + 1),
+                    cycle,
+                });
+                recipes.to_mut().index.insert(item, index + 1);
+            }
+            None => {
+                return Err(Error::ClosedLoop {
+                    product: product.clone(),
+                    cycle,
+                })
+            }
+        }
+    };
+
+    let trees = demands
+        .iter()
+        .map(|(name, rate, is_requested)| {
+            let scale = if *is_requested {
+                solution.requested_scale
+            } else {
+                solution.unrequested_scale
+            };
+            model.expand(&solution, name, rate * scale, &mut Vec::new())
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut totals = DependencyResolutionTotals::from(&trees);
+    for (product, product_recipes) in totals.recipes.iter_mut() {
+        for ((recipe, _), usage) in product_recipes.iter_mut() {
+            usage.name = recipes.map[product][*recipe]
+                .name
+                .clone()
+                .unwrap_or_else(|| product.clone());
+        }
+    }
+
+    Ok((trees, totals, fallbacks))
+}
+
+/// Parse a list of products and optional rates.
+/// Syntax is name[:rate][,name[:rate][...]]
+pub fn parse_product_list(raw: &str) -> Result<Vec<(String, Option<f32>)>, Error> {
+    let part_pattern = Regex::new(r"^([^:]*)(:\s*(\d+(\.\d+)?|\.\d+))?$").unwrap();
+    raw.split(",")
+        .map(
+            |part| match part_pattern.captures(part.trim().to_lowercase().as_str()) {
+                None => Err(Error::InvalidSyntax {
+                    input: part.trim().to_string(),
+                    expected: "name[:rate]",
+                }),
+                Some(captures) => Ok((
+                    captures.get(1).unwrap().as_str().trim().to_string(),
+                    captures.get(3).map(|m| m.as_str().parse().unwrap()),
+                )),
+            },
+        )
+        .collect()
+}
+
+/// Parse clock speeds for all machines or per product.
+/// Syntax is clock[,name:clock[,...]]
+pub fn parse_clock_speeds(raw: &str) -> Result<ClockSpeeds, Error> {
+    let mut clock_speeds = ClockSpeeds::default();
+    for part in raw.split(",") {
+        match part.trim().parse::<f32>() {
+            Ok(clock) => clock_speeds.default = clock,
+            Err(_) => {
+                for (product, clock) in parse_product_list(part)? {
+                    let clock =
+                        clock.ok_or_else(|| Error::MissingClockSpeed(part.trim().to_string()))?;
+                    clock_speeds.products.insert(product, clock);
+                }
+            }
+        }
+    }
+    Ok(clock_speeds)
+}
+
+/// Parse the resource nodes available for each resource.
+/// Syntax is resource: count purity[, count purity[, ...]][, resource: ...]
+pub fn parse_resource_nodes(raw: &str) -> Result<HashMap<String, ResourceNodes>, Error> {
+    let part_pattern = Regex::new(r"^(([^:]*):)?\s*(\d+)\s*(impure|normal|pure|wells?)$").unwrap();
+    let mut resource_nodes: HashMap<String, ResourceNodes> = HashMap::new();
+    let mut resource = None;
+    for part in raw.split(",") {
+        let part = part.trim().to_lowercase();
+        let invalid = || Error::InvalidSyntax {
+            input: part.clone(),
+            expected: "[resource:] count impure|normal|pure|wells",
+        };
+        let captures = part_pattern.captures(&part).ok_or_else(invalid)?;
+        if let Some(name) = captures.get(2) {
+            resource = Some(name.as_str().trim().to_string());
+        }
+        let Some(resource) = &resource else {
+            return Err(Error::MissingResource(part));
+        };
+        let nodes = resource_nodes.get_default(resource);
+        let count: u32 = captures[3].parse().map_err(|_| invalid())?;
+        let purity = match captures.get(4).unwrap().as_str() {
+            "impure" => Purity::Impure,
+            "normal" => Purity::Normal,
+            "pure" => Purity::Pure,
+            _ => {
+                *nodes.wells.get_or_insert(0) += count;
+                continue;
+            }
+        };
+        *nodes.nodes.get_default(&purity) += count;
+    }
+    Ok(resource_nodes)
+}
+
+impl Recipe {
+    fn is_alternate(&self) -> bool {
+        self.name
+            .as_ref()
+            .is_some_and(|name| name.starts_with("Alternate: "))
+    }
+
+    /// Whether this recipe goes by exactly `name`, ignoring case
+    fn has_name(&self, name: &str) -> bool {
+        self.name
+            .as_ref()
+            .is_some_and(|recipe_name| recipe_name.eq_ignore_ascii_case(name))
+    }
+
+    /// Whether this recipe goes by `name`, ignoring case and the "Alternate: " prefix
+    fn is_named(&self, name: &str) -> bool {
+        self.name.as_ref().is_some_and(|recipe_name| {
+            let recipe_name = recipe_name.to_lowercase();
+            recipe_name == name || recipe_name.strip_prefix("alternate: ") == Some(name)
+        })
+    }
+}
+
+/// Parse a list of recipe choices, each given by its number from `--list-recipes` or by its name.
+/// Syntax is name:recipe[,name:recipe[,...]]
+pub fn parse_recipe_selections(raw: &str) -> Result<Vec<(String, String)>, Error> {
+    let part_pattern = Regex::new(r"^([^:]*):\s*(.+)$").unwrap();
+    raw.split(",")
+        .map(
+            |part| match part_pattern.captures(part.trim().to_lowercase().as_str()) {
+                None => Err(Error::InvalidSyntax {
+                    input: part.trim().to_string(),
+                    expected: "name:recipe",
+                }),
+                Some(captures) => Ok((
+                    captures[1].trim().to_string(),
+                    captures[2].trim().to_string(),
+                )),
+            },
+        )
+        .collect()
+}
+
+fn read_config(file: &str) -> Result<String, Error> {
+    fs::read_to_string(file).map_err(|source| Error::ReadFailed {
+        file: file.to_string(),
+        source,
+    })
+}
+
+fn parse_config<T: for<'de> Deserialize<'de>>(file: &str, raw: &str) -> Result<T, Error> {
+    serde_json::from_str(raw).map_err(|source| Error::InvalidConfig {
+        file: file.to_string(),
+        source,
+    })
+}
+
+/// Read a config that may be left out, giving `None` if the file doesn't exist
+fn read_optional_config<T: for<'de> Deserialize<'de>>(file: &str) -> Result<Option<T>, Error> {
+    match fs::read_to_string(file) {
+        Ok(raw) => parse_config(file, &raw).map(Some),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(source) => Err(Error::ReadFailed {
+            file: file.to_string(),
+            source,
+        }),
+    }
+}
+
+/// Read the power each machine draws, if a machine config exists
+fn load_machines(file: &str) -> Result<HashMap<String, Machine>, Error> {
+    Ok(read_optional_config::<Vec<Machine>>(file)?
+        .unwrap_or_default()
+        .into_iter()
+        .map(|machine| (machine.name.clone(), machine))
+        .collect())
+}
+
+/// Read item forms & stack sizes, if an item config exists
+fn load_items(file: &str) -> Result<Option<Vec<Item>>, Error> {
+    read_optional_config(file)
+}
+
+/// Read power generators, if a generator config exists
+fn load_generators(file: &str) -> Result<Vec<Generator>, Error> {
+    Ok(read_optional_config(file)?.unwrap_or_default())
+}
+
+/// Add a power product for every generator, produced by burning each of its fuels
+fn add_generator_recipes(
+    recipes: &mut IndexedMap<String, Recipe>,
+    product_set: &mut HashSet<String>,
+    file: &str,
+) -> Result<(), Error> {
+    for generator in load_generators(file)? {
+        let mut recipe_list = generator.recipes();
+        for recipe in recipe_list.iter_mut() {
+            recipe.config = file.to_string();
+        }
+        product_set.extend(recipe_list.iter().flat_map(|recipe| {
+            recipe
+                .ingredients
+                .iter()
+                .chain(recipe.products.iter())
+                .map(|(item, _)| item.clone())
+        }));
+        recipes
+            .map
+            .get_default(&generator.product())
+            .extend(recipe_list);
+    }
+    Ok(())
+}
+
+/// Read the player's profile, if they have one
+fn load_profile(file: &str) -> Result<Option<Profile>, Error> {
+    read_optional_config(file)
+}
+
+/// Load every recipe from a stack of recipe configs, each one adding to, replacing or removing
+/// recipes from the ones before it, leaving out any that haven't been unlocked in the given profile
+fn load_recipes(
+    files: &[String],
+    profile: Option<&Profile>,
+) -> Result<(IndexedMap<String, Recipe>, HashSet<String>), Error> {
+    let mut recipe_list: Vec<Recipe> = Vec::new();
+    for file in files {
+        let entries = parse_config::<Vec<RecipeEntry>>(file, &read_config(file)?)?;
+
+        for entry in entries {
+            match entry {
+                RecipeEntry::Remove { remove } => {
+                    let recipe_count = recipe_list.len();
+                    recipe_list.retain(|recipe| !recipe.has_name(&remove));
+                    if recipe_list.len() == recipe_count {
+                        return Err(Error::UnknownRemovedRecipe {
+                            file: file.clone(),
+                            recipe: remove,
+                        });
+                    }
+                }
+                RecipeEntry::Recipe(mut recipe) => {
+                    recipe.config = file.clone();
+                    let replaced = recipe.name.as_ref().and_then(|name| {
+                        recipe_list
+                            .iter()
+                            .position(|existing| existing.has_name(name))
+                    });
+                    match replaced {
+                        Some(index) => recipe_list[index] = recipe,
+                        None => recipe_list.push(recipe),
+                    }
+                }
+            }
+        }
+    }
+
+    let recipe_map = recipe_list
+        .iter()
+        .filter(|recipe| profile.is_none_or(|profile| profile.unlocks(recipe)))
+        .cloned()
+        .flat_map(|recipe| {
+            recipe
+                .products
+                .iter()
+                .map(|(product, _)| (product.clone(), recipe.clone()))
+                .collect::<Vec<_>>()
+        })
+        .into();
+
+    let ingredient_set = recipe_list
+        .into_iter()
+        .flat_map(|recipe| {
+            recipe
+                .ingredients
+                .into_iter()
+                .map(|(ingredient, _)| ingredient)
+                .chain(
+                    recipe
+                        .products
+                        .into_iter()
+                        .map(|(product, _)| product)
+                        .collect::<Vec<_>>(),
+                )
+        })
+        .collect();
+
+    Ok((recipe_map, ingredient_set))
+}
+
+/// Read a file that may be encoded as UTF-16, as the game's Docs.json is
+fn read_utf16_or_utf8(file: &str) -> Result<String, Error> {
+    let bytes = fs::read(file).map_err(|source| Error::ReadFailed {
+        file: file.to_string(),
+        source,
+    })?;
+    let utf16 = |bytes: &[u8], from_bytes: fn([u8; 2]) -> u16| {
+        String::from_utf16(
+            &bytes
+                .chunks_exact(2)
+                .map(|pair| from_bytes([pair[0], pair[1]]))
+                .collect::<Vec<_>>(),
+        )
+        .map_err(|_| Error::InvalidEncoding {
+            file: file.to_string(),
+        })
+    };
+    match bytes.as_slice() {
+        [0xFF, 0xFE, rest @ ..] => utf16(rest, u16::from_le_bytes),
+        [0xFE, 0xFF, rest @ ..] => utf16(rest, u16::from_be_bytes),
+        [0xEF, 0xBB, 0xBF, rest @ ..] => Ok(String::from_utf8_lossy(rest).into_owned()),
+        _ => Ok(String::from_utf8_lossy(&bytes).into_owned()),
+    }
+}
+
+/// Every class in Docs.json whose native class ends with one of `native_classes`
+fn docs_classes<'a>(
+    docs: &'a serde_json::Value,
+    native_classes: &'a [&str],
+) -> impl Iterator<Item = &'a serde_json::Value> + 'a {
+    docs.as_array()
+        .into_iter()
+        .flatten()
+        .filter(move |group| {
+            group["NativeClass"].as_str().is_some_and(|native_class| {
+                native_classes
+                    .iter()
+                    .any(|class| native_class.ends_with(&format!(".{}'", class)))
+            })
+        })
+        .flat_map(|group| group["Classes"].as_array().into_iter().flatten())
+}
+
+fn docs_str<'a>(class: &'a serde_json::Value, field: &str) -> &'a str {
+    class[field].as_str().unwrap_or_default()
+}
+
+fn docs_f32(class: &serde_json::Value, field: &str) -> f32 {
+    docs_str(class, field).parse().unwrap_or_default()
+}
+
+/// Class names referenced in a field, such as `Desc_IronPlate_C` out of an asset path
+fn docs_class_references(class: &serde_json::Value, field: &str, prefix: &str) -> Vec<String> {
+    Regex::new(&format!(r"\.({}[A-Za-z0-9_]*)", prefix))
+        .unwrap()
+        .captures_iter(docs_str(class, field))
+        .map(|captures| captures[1].to_string())
+        .collect()
+}
+
+const ITEM_DESCRIPTOR_CLASSES: [&str; 11] = [
+    "FGItemDescriptor",
+    "FGResourceDescriptor",
+    "FGItemDescriptorBiomass",
+    "FGItemDescriptorNuclearFuel",
+    "FGItemDescriptorPowerBoosterFuel",
+    "FGPowerShardDescriptor",
+    "FGConsumableDescriptor",
+    "FGEquipmentDescriptor",
+    "FGAmmoTypeProjectile",
+    "FGAmmoTypeSpreadshot",
+    "FGAmmoTypeInstantHit",
+];
+const MANUFACTURER_CLASSES: [&str; 2] = [
+    "FGBuildableManufacturer",
+    "FGBuildableManufacturerVariablePower",
+];
+const VARIABLE_POWER_MANUFACTURER_CLASS: &str = "FGBuildableManufacturerVariablePower";
+
+/// Recipes, machines & items read out of the game's Docs.json
+pub type DocsImport = (Vec<Recipe>, Vec<Machine>, Vec<Item>);
+
+/// Parse the game's Docs.json into recipes, machines & items
+pub fn import_docs(file: &str) -> Result<DocsImport, Error> {
+    let docs: serde_json::Value = parse_config(file, &read_utf16_or_utf8(file)?)?;
+
+    // items, keyed by class name
+    let items: HashMap<String, Item> = docs_classes(&docs, &ITEM_DESCRIPTOR_CLASSES)
+        .filter_map(|class| {
+            let form = match docs_str(class, "mForm") {
+                "RF_SOLID" => ItemForm::Solid,
+                "RF_LIQUID" => ItemForm::Liquid,
+                "RF_GAS" => ItemForm::Gas,
+                _ => return None,
+            };
+            let stack_size = match docs_str(class, "mStackSize") {
+                "SS_ONE" => 1,
+                "SS_SMALL" => 50,
+                "SS_MEDIUM" => 100,
+                "SS_BIG" => 200,
+                "SS_HUGE" => 500,
+                "SS_FLUID" => 50000,
+                _ => 0,
+            };
+            Some((
+                docs_str(class, "ClassName").to_string(),
+                Item {
+                    name: docs_str(class, "mDisplayName").to_string(),
+                    form,
+                    stack_size,
+                },
+            ))
+        })
+        .collect();
+
+    // machines, keyed by class name
+    let machines: HashMap<String, Machine> = docs_classes(&docs, &MANUFACTURER_CLASSES)
+        .map(|class| {
+            let power = match docs_f32(class, "mPowerConsumption") {
+                0.0 => {
+                    (docs_f32(class, "mEstimatedMininumPowerConsumption")
+                        + docs_f32(class, "mEstimatedMaximumPowerConsumption"))
+                        / 2.0
+                }
+                power => power,
+            };
+            (
+                docs_str(class, "ClassName").to_string(),
+                Machine {
+                    name: docs_str(class, "mDisplayName").to_string(),
+                    power,
+                },
+            )
+        })
+        .collect();
+
+    let variable_power_machines: HashSet<String> =
+        docs_classes(&docs, &[VARIABLE_POWER_MANUFACTURER_CLASS])
+            .map(|class| docs_str(class, "mDisplayName").to_string())
+            .collect();
+
+    // the milestone or research that unlocks each recipe; tutorial & alternate unlocks are left out,
+    // since tutorial recipes are available from the start and alternates are unlocked by name
+    let unlocks: HashMap<String, String> = docs_classes(&docs, &["FGSchematic"])
+        .filter_map(|class| {
+            let unlock = match docs_str(class, "mType") {
+                "EST_Milestone" => format!(
+                    "Tier {} - {}",
+                    docs_str(class, "mTechTier"),
+                    docs_str(class, "mDisplayName")
+                ),
+                "EST_MAM" => docs_str(class, "mDisplayName").to_string(),
+                _ => return None,
+            };
+            Some((class, unlock))
+        })
+        .flat_map(|(class, unlock)| {
+            class["mUnlocks"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .flat_map(|unlocks| docs_class_references(unlocks, "mRecipes", "Recipe_"))
+                .map(move |recipe| (recipe, unlock.clone()))
+        })
+        .collect();
+
+    let item_amounts = |class: &serde_json::Value, field: &str, duration: f32| {
+        Regex::new(r#"ItemClass=[^,]*?\.([A-Za-z0-9_]+)["']*,\s*Amount=([\d.]+)"#)
+            .unwrap()
+            .captures_iter(docs_str(class, field))
+            .map(|captures| {
+                let item = items
+                    .get(&captures[1])
+                    .ok_or_else(|| Error::UnknownDocsItem {
+                        file: file.to_string(),
+                        item: captures[1].to_string(),
+                    })?;
+                // fluids are measured in liters
+                let amount: f32 = captures[2].parse().map_err(|_| Error::InvalidDocsAmount {
+                    file: file.to_string(),
+                    item: item.name.clone(),
+                    amount: captures[2].to_string(),
+                })?;
+                let amount = match item.form {
+                    ItemForm::Solid => amount,
+                    _ => amount / 1000.0,
+                };
+                Ok((item.name.clone(), amount * 60.0 / duration))
+            })
+            .collect::<Result<Vec<_>, Error>>()
+    };
+
+    // only recipes made in a production machine are kept, leaving out the build gun & workbenches
+    let recipes = docs_classes(&docs, &["FGRecipe"])
+        .filter_map(|class| {
+            let machine = docs_class_references(class, "mProducedIn", "Build_")
+                .into_iter()
+                .find_map(|machine| machines.get(&machine))?;
+            Some((class, machine))
+        })
+        .map(|(class, machine)| {
+            let duration = docs_f32(class, "mManufactoringDuration");
+            // machines with variable power draw average out over the recipe's power cycle
+            let variable_power = variable_power_machines.contains(&machine.name).then(|| {
+                docs_f32(class, "mVariablePowerConsumptionConstant")
+                    + docs_f32(class, "mVariablePowerConsumptionFactor") / 2.0
+            });
+            Ok(Recipe {
+                name: Some(docs_str(class, "mDisplayName").to_string()),
+                machine: machine.name.clone(),
+                ingredients: item_amounts(class, "mIngredients", duration)?,
+                products: item_amounts(class, "mProduct", duration)?,
+                power: variable_power,
+                unlock: unlocks.get(docs_str(class, "ClassName")).cloned(),
+                config: String::new(),
+            })
+        })
+        .collect::<Result<_, Error>>()?;
+
+    let mut machines: Vec<Machine> = machines.into_values().collect();
+    machines.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut items: Vec<Item> = items.into_values().collect();
+    items.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok((recipes, machines, items))
+}
+
+pub fn write_config<T: Serialize>(file: &str, value: &T) -> Result<(), Error> {
+    fs::write(file, serde_json::to_string_pretty(value).unwrap()).map_err(|source| {
+        Error::WriteFailed {
+            file: file.to_string(),
+            source,
+        }
+    })
+}
+
+/// A product found by name with [`Recipes::find_product`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProductMatch {
+    /// The product's full name
+    pub name: String,
+    /// Whether the name given was misspelled, and corrected to this product
+    pub corrected: bool,
+}
+
+/// Most product names suggested for an unknown one
+const MAX_SUGGESTIONS: usize = 3;
+
+/// The lowercase first letter of each word in a name
+fn initials(name: &str) -> String {
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter_map(|word| word.chars().next())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Read product aliases, if an alias config exists, keyed by lowercase alias
+fn load_aliases(file: &str, products: &HashSet<String>) -> Result<HashMap<String, String>, Error> {
+    let Some(aliases) = read_optional_config::<HashMap<String, String>>(file)? else {
+        return Ok(HashMap::new());
+    };
+    aliases
+        .into_iter()
+        .map(|(alias, product)| {
+            let full_name = products
+                .iter()
+                .find(|full_name| full_name.eq_ignore_ascii_case(product.trim()))
+                .ok_or_else(|| Error::InvalidAlias {
+                    file: file.to_string(),
+                    alias: alias.clone(),
+                    product: product.clone(),
+                })?;
+            Ok((alias.trim().to_lowercase(), full_name.clone()))
+        })
+        .collect()
+}
+
+/// Every recipe, machine & item the planner knows about, loaded with a [`RecipesLoader`]
+#[derive(Debug, Clone)]
+pub struct Recipes {
+    recipes: IndexedMap<String, Recipe>,
+    products: HashSet<String>,
+    machines: HashMap<String, Machine>,
+    /// items carried by pipes instead of belts
+    fluids: HashSet<String>,
+    /// products keyed by their lowercase aliases
+    aliases: HashMap<String, String>,
+    abbreviations: bool,
+}
+
+impl Recipes {
+    /// The full name of a product, looked up ignoring case. Aliases are accepted, as are
+    /// abbreviations if enabled, and a misspelled name is corrected if only one product is close
+    /// to it; otherwise, the error suggests the closest names
+    pub fn product_name(&self, name: &str) -> Result<String, Error> {
+        self.find_product(name).map(|found| found.name)
+    }
+
+    /// Look up a product the same way as [`Recipes::product_name`], telling whether its name was
+    /// a typo that had to be corrected
+    pub fn find_product(&self, name: &str) -> Result<ProductMatch, Error> {
+        let exact = |name: &str| ProductMatch {
+            name: name.to_string(),
+            corrected: false,
+        };
+        let name = name.trim().to_lowercase();
+        let products = self.products();
+        if let Some(product) = products
+            .iter()
+            .find(|full_name| full_name.to_lowercase() == name)
+        {
+            return Ok(exact(product));
+        }
+        if let Some(product) = self.aliases.get(&name) {
+            return Ok(exact(product));
+        }
+        if name.is_empty() {
+            return Err(Error::UnknownProduct {
+                name,
+                suggestions: Vec::new(),
+            });
+        }
+
+        // abbreviations are the initials of each word, e.g. hmf for Heavy Modular Frame
+        let abbreviated: Vec<_> = products
+            .iter()
+            .filter(|product| initials(product).len() > 1 && initials(product) == name)
+            .collect();
+        if self.abbreviations {
+            match abbreviated.as_slice() {
+                [] => (),
+                [product] => return Ok(exact(product)),
+                _ => {
+                    return Err(Error::UnknownProduct {
+                        name,
+                        suggestions: abbreviated
+                            .iter()
+                            .map(|product| product.to_string())
+                            .collect(),
+                    })
+                }
+            }
+        }
+
+        // take the closest name to be meant if no other is as close, and it's only a typo or two off
+        let distances: Vec<_> = products
+            .iter()
+            .map(|product| {
+                (
+                    strsim::damerau_levenshtein(&name, &product.to_lowercase()),
+                    *product,
+                )
+            })
+            .collect();
+        let allowed = (name.chars().count() / 6).max(1);
+        if let Some((closest, product)) = distances.iter().min() {
+            let ties = distances
+                .iter()
+                .filter(|(distance, _)| distance == closest)
+                .count();
+            if *closest <= allowed && ties == 1 {
+                return Ok(ProductMatch {
+                    name: product.to_string(),
+                    corrected: true,
+                });
+            }
+        }
+
+        // suggest names abbreviated as the one given, names containing it, then names that are
+        // spelled similarly
+        let mut containing: Vec<_> = products
+            .iter()
+            .filter(|product| product.to_lowercase().contains(&name))
+            .collect();
+        containing.sort_by_key(|product| product.len());
+        let mut similar: Vec<_> = products
+            .iter()
+            .map(|product| {
+                (
+                    strsim::normalized_damerau_levenshtein(&name, &product.to_lowercase()),
+                    product,
+                )
+            })
+            .filter(|(similarity, _)| *similarity >= 0.5)
+            .collect();
+        similar.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        let mut suggestions: Vec<String> = Vec::new();
+        for product in abbreviated
+            .into_iter()
+            .chain(containing)
+            .chain(similar.into_iter().map(|(_, product)| product))
+        {
+            if suggestions.len() < MAX_SUGGESTIONS && !suggestions.iter().any(|s| s == product) {
+                suggestions.push(product.to_string());
+            }
+        }
+        Err(Error::UnknownProduct { name, suggestions })
+    }
+
+    /// Every product that can be planned, in alphabetical order
+    pub fn products(&self) -> Vec<&str> {
+        let mut products: Vec<_> = self.products.iter().map(String::as_str).collect();
+        products.sort();
+        products
+    }
+
+    /// Every recipe that produces a product, in the order they can be chosen in
+    pub fn recipes_for(&self, product: &str) -> &[Recipe] {
+        self.recipes
+            .map
+            .get(product)
+            .map_or(&[], |product_recipes| product_recipes.as_slice())
+    }
+
+    /// Every recipe that consumes an item, each with the product it's listed under and its index
+    /// among that product's recipes, sorted by product. Recipes with byproducts are listed once,
+    /// under their main product
+    pub fn recipes_using(&self, item: &str) -> Vec<(&str, usize, &Recipe)> {
+        let mut uses: Vec<_> = self
+            .recipes
+            .map
+            .iter()
+            .flat_map(|(product, product_recipes)| {
+                product_recipes
+                    .iter()
+                    .enumerate()
+                    .filter(move |(_, recipe)| {
+                        recipe.products.first().map(|(main, _)| main) == Some(product)
+                            && recipe
+                                .ingredients
+                                .iter()
+                                .any(|(ingredient, _)| ingredient == item)
+                    })
+                    .map(move |(index, recipe)| (product.as_str(), index, recipe))
+            })
+            .collect();
+        uses.sort_by(|(a, a_index, _), (b, b_index, _)| a.cmp(b).then(a_index.cmp(b_index)));
+        uses
+    }
+
+    /// The index of the recipe chosen for a product, either by its number from `--list-recipes`
+    /// or by its name
+    pub fn select_recipe(&self, product: &str, selection: &str) -> Result<usize, Error> {
+        let product_recipes = self.recipes_for(product);
+        if product_recipes.is_empty() {
+            return Err(Error::NoRecipes(product.to_string()));
+        }
+        match selection.parse::<usize>() {
+            Ok(number) => number
+                .checked_sub(1)
+                .filter(|index| *index < product_recipes.len())
+                .ok_or_else(|| Error::InvalidRecipeIndex {
+                    product: product.to_string(),
+                    number,
+                    count: product_recipes.len(),
+                }),
+            Err(_) => product_recipes
+                .iter()
+                .position(|recipe| recipe.is_named(&selection.to_lowercase()))
+                .ok_or_else(|| Error::UnknownRecipe {
+                    product: product.to_string(),
+                    name: selection.to_string(),
+                    recipes: product_recipes
+                        .iter()
+                        .filter_map(|recipe| recipe.name.clone())
+                        .collect(),
+                }),
+        }
+    }
+}
+
+/// Loads [`Recipes`] from a set of config files, defaulting to the files shipped with the planner
+#[derive(Debug, Clone)]
+pub struct RecipesLoader {
+    recipe_configs: Vec<String>,
+    machine_config: String,
+    generator_config: String,
+    item_config: String,
+    profile: String,
+    alias_config: String,
+    abbreviations: bool,
+}
+
+impl Default for RecipesLoader {
+    fn default() -> Self {
+        RecipesLoader {
+            recipe_configs: vec!["recipes.json".to_string()],
+            machine_config: "machines.json".to_string(),
+            generator_config: "generators.json".to_string(),
+            item_config: "items.json".to_string(),
+            profile: "profile.json".to_string(),
+            alias_config: "aliases.json".to_string(),
+            abbreviations: false,
+        }
+    }
+}
+
+impl RecipesLoader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Recipe configs to merge, each one adding to, replacing or removing recipes from the ones
+    /// before it
+    pub fn recipe_configs<I: IntoIterator<Item = S>, S: Into<String>>(
+        &mut self,
+        files: I,
+    ) -> &mut Self {
+        self.recipe_configs = files.into_iter().map(Into::into).collect();
+        self
+    }
+
+    pub fn machine_config(&mut self, file: impl Into<String>) -> &mut Self {
+        self.machine_config = file.into();
+        self
+    }
+
+    pub fn generator_config(&mut self, file: impl Into<String>) -> &mut Self {
+        self.generator_config = file.into();
+        self
+    }
+
+    /// Item config used to tell solids & fluids apart; a built-in list is used if it doesn't exist
+    pub fn item_config(&mut self, file: impl Into<String>) -> &mut Self {
+        self.item_config = file.into();
+        self
+    }
+
+    /// Profile of unlocked milestones, MAM research & alternate recipes; every recipe is used if
+    /// it doesn't exist
+    pub fn profile(&mut self, file: impl Into<String>) -> &mut Self {
+        self.profile = file.into();
+        self
+    }
+
+    /// Alternative names for products, as an object of aliases & the products they stand for; no
+    /// aliases are used if it doesn't exist
+    pub fn alias_config(&mut self, file: impl Into<String>) -> &mut Self {
+        self.alias_config = file.into();
+        self
+    }
+
+    /// Accept the initials of a product's name in place of the full name
+    pub fn abbreviations(&mut self, abbreviations: bool) -> &mut Self {
+        self.abbreviations = abbreviations;
+        self
+    }
+
+    pub fn load(&self) -> Result<Recipes, Error> {
+        let profile = load_profile(&self.profile)?;
+        let (mut recipes, mut products) = load_recipes(&self.recipe_configs, profile.as_ref())?;
+        add_generator_recipes(&mut recipes, &mut products, &self.generator_config)?;
+        let fluids = load_items(&self.item_config)?.map_or_else(
+            || FLUIDS.iter().map(|fluid| fluid.to_string()).collect(),
+            |items| {
+                items
+                    .into_iter()
+                    .filter(|item| item.form != ItemForm::Solid)
+                    .map(|item| item.name)
+                    .collect()
+            },
+        );
+        Ok(Recipes {
+            recipes,
+            machines: load_machines(&self.machine_config)?,
+            fluids,
+            aliases: load_aliases(&self.alias_config, &products)?,
+            abbreviations: self.abbreviations,
+            products,
+        })
+    }
+}
+
+/// Everything asked of a factory plan; products are given by name, ignoring case, and are checked
+/// against the [`Recipes`] the request is planned with
+#[derive(Debug, Clone, Default)]
+pub struct PlanRequest {
+    wants: Vec<(String, Option<f32>)>,
+    haves: Vec<(String, Option<f32>)>,
+    recipes: Vec<(String, String)>,
+    resupply_insufficient: bool,
+    reuse_byproducts: bool,
+    optimize: bool,
+    clock_speeds: Option<ClockSpeeds>,
+    extraction: bool,
+    nodes: HashMap<String, ResourceNodes>,
+    miner: MinerTier,
+    throughput: bool,
+    max_belt: Option<BeltTier>,
+    max_pipe: Option<PipeTier>,
+}
+
+impl PlanRequest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A product to make, at a given rate, or as much as the available ingredients allow
+    pub fn want(&mut self, product: impl Into<String>, rate: Option<f32>) -> &mut Self {
+        self.wants.push((product.into(), rate));
+        self
+    }
+
+    /// Products to make, with their rates if given
+    pub fn wants(&self) -> &[(String, Option<f32>)] {
+        &self.wants
+    }
+
+    /// Stop making a product; returns whether it was wanted
+    pub fn remove_want(&mut self, product: &str) -> bool {
+        remove_named(&mut self.wants, product)
+    }
+
+    /// An ingredient that's available, at a given rate, or without limit
+    pub fn have(&mut self, ingredient: impl Into<String>, rate: Option<f32>) -> &mut Self {
+        self.haves.push((ingredient.into(), rate));
+        self
+    }
+
+    /// Stop supplying an ingredient; returns whether it was available
+    pub fn remove_have(&mut self, ingredient: &str) -> bool {
+        remove_named(&mut self.haves, ingredient)
+    }
+
+    /// Make a product with the given recipe, chosen by its number from `--list-recipes` or by its
+    /// name
+    pub fn use_recipe(
+        &mut self,
+        product: impl Into<String>,
+        recipe: impl Into<String>,
+    ) -> &mut Self {
+        self.recipes.push((product.into(), recipe.into()));
+        self
+    }
+
+    /// Go back to the default recipe for a product; returns whether a recipe had been chosen
+    pub fn remove_recipe(&mut self, product: &str) -> bool {
+        remove_named(&mut self.recipes, product)
+    }
+
+    /// If not enough input resources are available, then resupply more to fulfill the requested quota
+    pub fn resupply_insufficient(&mut self, resupply_insufficient: bool) -> &mut Self {
+        self.resupply_insufficient = resupply_insufficient;
+        self
+    }
+
+    /// Allow the reuse of byproduct outputs from the system as inputs
+    pub fn reuse_byproducts(&mut self, reuse_byproducts: bool) -> &mut Self {
+        self.reuse_byproducts = reuse_byproducts;
+        self
+    }
+
+    /// Choose the combination of alternate recipes that uses the least raw resources
+    pub fn optimize(&mut self, optimize: bool) -> &mut Self {
+        self.optimize = optimize;
+        self
+    }
+
+    pub fn clock_speeds(&mut self, clock_speeds: ClockSpeeds) -> &mut Self {
+        self.clock_speeds = Some(clock_speeds);
+        self
+    }
+
+    /// Plan the miners & extractors needed to supply raw resources
+    pub fn extraction(&mut self, extraction: bool) -> &mut Self {
+        self.extraction = extraction;
+        self
+    }
+
+    /// Resource nodes available for extraction; implies extraction
+    pub fn resource_nodes(
+        &mut self,
+        resource: impl Into<String>,
+        nodes: ResourceNodes,
+    ) -> &mut Self {
+        self.nodes.get_default(&resource.into()).merge(&nodes);
+        self
+    }
+
+    pub fn miner(&mut self, miner: MinerTier) -> &mut Self {
+        self.miner = miner;
+        self
+    }
+
+    /// Work out the belts & pipes needed to carry each item
+    pub fn throughput(&mut self, throughput: bool) -> &mut Self {
+        self.throughput = throughput;
+        self
+    }
+
+    /// Fastest conveyor belt available; implies throughput
+    pub fn max_belt(&mut self, max_belt: BeltTier) -> &mut Self {
+        self.max_belt = Some(max_belt);
+        self
+    }
+
+    /// Fastest pipeline available; implies throughput
+    pub fn max_pipe(&mut self, max_pipe: PipeTier) -> &mut Self {
+        self.max_pipe = Some(max_pipe);
+        self
+    }
+
+    /// Plan a factory for this request
+    pub fn plan(&self, recipes: &Recipes) -> Result<Plan, Error> {
+        if self.wants.is_empty() {
+            return Err(Error::NothingWanted);
+        }
+
+        // look up products by name, keeping track of any misspelled names that were corrected
+        let mut corrections: Vec<(String, String)> = Vec::new();
+        let mut product_name = |name: &str| {
+            let found = recipes.find_product(name)?;
+            let correction = (name.trim().to_string(), found.name.clone());
+            if found.corrected && !corrections.contains(&correction) {
+                corrections.push(correction);
+            }
+            Ok::<_, Error>(found.name)
+        };
+        let mut want_list = Vec::new();
+        for (name, rate) in &self.wants {
+            want_list.push((product_name(name)?, *rate));
+        }
+        let mut have_list = Vec::new();
+        for (name, rate) in &self.haves {
+            have_list.push((product_name(name)?, *rate));
+        }
+
+        // read recipe overrides
+        let mut recipe_map = Cow::Borrowed(&recipes.recipes);
+        for (product, selection) in &self.recipes {
+            let product = product_name(product)?;
+            let index = recipes.select_recipe(&product, selection)?;
+            recipe_map.to_mut().index.insert(product, index);
+        }
+
+        // Compute recipe dependencies
+        let (mut trees, mut totals, fallbacks) = resolve_dependency_trees(
+            &recipe_map,
+            &recipes.machines,
+            want_list,
+            have_list,
+            self.resupply_insufficient,
+            self.reuse_byproducts,
+            self.optimize,
+        )?;
+
+        // Run machines at the requested clock speeds, within the limits of the available belts & pipes
+        let logistics = (self.throughput || self.max_belt.is_some() || self.max_pipe.is_some())
+            .then(|| Logistics {
+                max_belt: self.max_belt.unwrap_or(BeltTier::Mk6),
+                max_pipe: self.max_pipe.unwrap_or(PipeTier::Mk2),
+                fluids: recipes.fluids.clone(),
+            });
+        let limit_logistics = self.max_belt.is_some() || self.max_pipe.is_some();
+        if self.clock_speeds.is_some() || limit_logistics {
+            let mut clock_speeds = ClockSpeeds::default();
+            if let Some(requested) = &self.clock_speeds {
+                clock_speeds.default = requested.default;
+                for (product, clock) in &requested.products {
+                    clock_speeds.products.insert(product_name(product)?, *clock);
+                }
+            }
+            for clock in clock_speeds
+                .products
+                .values()
+                .chain([&clock_speeds.default])
+            {
+                if !(1.0..=MAX_CLOCK_SPEED).contains(clock) {
+                    return Err(Error::InvalidClockSpeed(*clock));
+                }
+            }
+            for node in trees.iter_mut() {
+                apply_clock_speeds(
+                    node,
+                    &clock_speeds,
+                    logistics.as_ref().filter(|_| limit_logistics),
+                );
+            }
+            totals = DependencyResolutionTotals::from(&trees);
+        }
+
+        // Plan extractors for raw resources
+        if self.extraction || !self.nodes.is_empty() {
+            let mut nodes: HashMap<String, ResourceNodes> = HashMap::new();
+            for (resource, resource_nodes) in &self.nodes {
+                nodes
+                    .get_default(&product_name(resource)?)
+                    .merge(resource_nodes);
+            }
+            totals.plan_extraction(&nodes, self.miner);
+        }
+
+        Ok(Plan {
+            trees,
+            totals,
+            logistics,
+            fallbacks,
+            corrections,
+            optimize: self.optimize,
+        })
+    }
+
+    /// Every product that can be made from the available ingredients alone, without resupplying
+    /// anything else, each with the most of it that can be made on its own, or `None` if nothing
+    /// limits it. Products wanted by the request are ignored
+    pub fn buildable(&self, recipes: &Recipes) -> Result<Vec<(String, Option<f32>)>, Error> {
+        let haves = self
+            .haves
+            .iter()
+            .map(|(name, rate)| Ok((recipes.product_name(name)?, *rate)))
+            .collect::<Result<HashMap<_, _>, Error>>()?;
+        let mut request = self.clone();
+        request.haves = haves.clone().into_iter().collect();
+        request.resupply_insufficient = false;
+
+        let mut buildable = Vec::new();
+        for product in recipes.products() {
+            if haves.contains_key(product) || recipes.recipes_for(product).is_empty() {
+                continue;
+            }
+
+            // make as much as the ingredients allow, as for any product without a requested rate
+            request.wants = vec![(product.to_string(), None)];
+            let plan = match request.plan(recipes) {
+                Ok(plan) => plan,
+                Err(Error::ClosedLoop { .. } | Error::DivergentLoop(_) | Error::Unbalanced(_)) => {
+                    continue
+                }
+                Err(err) => return Err(err),
+            };
+            let inputs: Vec<_> = plan
+                .totals
+                .inputs
+                .iter()
+                .filter(|(_, quantity)| **quantity > FLOW_EPSILON as f32)
+                .map(|(input, _)| input)
+                .collect();
+            let rate = plan.totals.outputs.get(product).copied().unwrap_or(0.0);
+            if inputs.is_empty()
+                || inputs.iter().any(|input| !haves.contains_key(*input))
+                || rate <= FLOW_EPSILON as f32
+            {
+                continue;
+            }
+            let unlimited = inputs.iter().all(|input| haves[*input].is_none());
+            buildable.push((product.to_string(), (!unlimited).then_some(rate)));
+        }
+        Ok(buildable)
+    }
+}
+
+/// Remove every entry for a product from a list, ignoring case; returns whether any were removed
+fn remove_named<T>(list: &mut Vec<(String, T)>, product: &str) -> bool {
+    let count = list.len();
+    list.retain(|(name, _)| !name.trim().eq_ignore_ascii_case(product.trim()));
+    list.len() != count
+}
+
+/// A product and the rate it's wanted or available at, as written in a [`PlanFile`]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProductRate {
+    pub product: String,
+    /// Items per minute; as much as possible for a want, or without limit for a have
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        deserialize_with = "deserialize_rate"
+    )]
+    pub rate: Option<f32>,
+}
+
+/// Rates can't be negative, the same as on the command line
+fn deserialize_rate<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<f32>, D::Error> {
+    match Option::<f32>::deserialize(deserializer)? {
+        Some(rate) if !(rate.is_finite() && rate >= 0.0) => Err(serde::de::Error::custom(format!(
+            "rate must be a number of at least 0, not {}",
+            rate
+        ))),
+        rate => Ok(rate),
+    }
+}
+
+/// A plan kept in a TOML or JSON file, to be planned again with `plan run`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PlanFile {
+    pub want: Vec<ProductRate>,
+    pub have: Vec<ProductRate>,
+    /// The recipe to make each product with, given by its number from `--list-recipes` or by its
+    /// name
+    #[serde(deserialize_with = "deserialize_recipe_selections")]
+    pub recipes: BTreeMap<String, String>,
+    pub show_perfect_splits: bool,
+    pub resupply_insufficient: bool,
+    pub reuse_byproducts: bool,
+}
+
+/// Recipe selections may be written as numbers as well as names
+fn deserialize_recipe_selections<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<BTreeMap<String, String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Selection {
+        Number(usize),
+        Name(String),
+    }
+
+    Ok(BTreeMap::<String, Selection>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(product, selection)| match selection {
+            Selection::Number(number) => (product, number.to_string()),
+            Selection::Name(name) => (product, name),
+        })
+        .collect())
+}
+
+/// Whether a plan file is TOML or JSON, going by its extension
+fn is_json_plan(file: &str) -> Result<bool, Error> {
+    match Path::new(file)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase())
+        .as_deref()
+    {
+        Some("toml") => Ok(false),
+        Some("json") => Ok(true),
+        _ => Err(Error::UnknownPlanFormat(file.to_string())),
+    }
+}
+
+impl PlanFile {
+    /// Read a plan from a `.toml` or `.json` file
+    pub fn read(file: &str) -> Result<Self, Error> {
+        let json = is_json_plan(file)?;
+        let raw = read_config(file)?;
+        match json {
+            true => parse_config(file, &raw),
+            false => toml::from_str(&raw).map_err(|source| Error::InvalidPlanFile {
+                file: file.to_string(),
+                source,
+            }),
+        }
+    }
+
+    /// Write the plan to a `.toml` or `.json` file, overwriting it
+    pub fn write(&self, file: &str) -> Result<(), Error> {
+        match is_json_plan(file)? {
+            true => write_config(file, self),
+            false => fs::write(file, toml::to_string_pretty(self).unwrap()).map_err(|source| {
+                Error::WriteFailed {
+                    file: file.to_string(),
+                    source,
+                }
+            }),
+        }
+    }
+
+    /// A request for the factory this plan describes
+    pub fn request(&self) -> PlanRequest {
+        let mut request = PlanRequest::new();
+        for ProductRate { product, rate } in &self.want {
+            request.want(product, *rate);
+        }
+        for ProductRate { product, rate } in &self.have {
+            request.have(product, *rate);
+        }
+        for (product, recipe) in &self.recipes {
+            request.use_recipe(product, recipe);
+        }
+        request
+            .resupply_insufficient(self.resupply_insufficient)
+            .reuse_byproducts(self.reuse_byproducts);
+        request
+    }
+}
+
+/// A planned factory: a dependency tree for each requested product, and the totals across them
+#[derive(Debug, Clone)]
+pub struct Plan {
+    pub trees: Vec<Product>,
+    pub totals: DependencyResolutionTotals,
+    /// The belts & pipes available, if throughput was asked for
+    pub logistics: Option<Logistics>,
+    /// Recipes passed over for the next recipe of their product, as they could never get started
+    pub fallbacks: Vec<RecipeFallback>,
+    /// Misspelled product names in the request, and the products they were taken to mean
+    pub corrections: Vec<(String, String)>,
+    optimize: bool,
+}
+
+impl Plan {
+    /// Misspelled names that were corrected, and recipes that were passed over, to point out
+    /// alongside the plan
+    pub fn notes(&self) -> Vec<String> {
+        self.corrections
+            .iter()
+            .map(|(name, product)| format!("using '{}' for '{}'", product, name))
+            .chain(self.fallbacks.iter().map(|fallback| fallback.to_string()))
+            .collect()
+    }
+
+    /// The plan as a graph of production lines & the items flowing between them
+    pub fn graph(&self) -> ProductionGraph {
+        ProductionGraph::from(&self.trees)
+    }
+
+    /// Show the plan the same way the command line does
+    pub fn display(&self, show_perfect_splits: bool) -> PlanDisplay<'_> {
+        PlanDisplay {
+            plan: self,
+            show_perfect_splits,
+            merged: false,
+            section: None,
+        }
+    }
+}
+
+impl Serialize for Plan {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut plan = serializer.serialize_struct("Plan", 7)?;
+        plan.serialize_field("trees", &self.trees)?;
+        plan.serialize_field("totals", &self.totals)?;
+        plan.serialize_field("total_power", &self.totals.total_power())?;
+        plan.serialize_field("power_generated", &self.totals.power_generated())?;
+        plan.serialize_field("power_shards", &self.totals.power_shards())?;
+        plan.serialize_field("fallbacks", &self.fallbacks)?;
+        plan.serialize_field("corrections", &self.corrections)?;
+        plan.end()
+    }
+}
+
+impl Display for Plan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.display(false).fmt(f)
+    }
+}
+
+/// A single part of a plan to show
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlanSection {
+    /// The dependency tree of each product
+    Tree,
+    /// Merged production lines & where their products go
+    Lines,
+    /// Ingredients, products, machines & power
+    Totals,
+    /// Machines, extractors & power
+    Machines,
+}
+
+pub struct PlanDisplay<'a> {
+    plan: &'a Plan,
+    show_perfect_splits: bool,
+    /// show merged production lines instead of the tree
+    merged: bool,
+    /// show only one part of the plan
+    section: Option<PlanSection>,
+}
+
+impl PlanDisplay<'_> {
+    /// Show every production line making the same product with the same recipe merged into one,
+    /// with the consumers it supplies, instead of the tree
+    pub fn merged(mut self, merged: bool) -> Self {
+        self.merged = merged;
+        self
+    }
+
+    /// Show only one part of the plan
+    pub fn section(mut self, section: PlanSection) -> Self {
+        self.section = Some(section);
+        self
+    }
+
+    fn fmt_tree(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Tree:")?;
+        for node in &self.plan.trees {
+            writeln!(
+                f,
+                "{}",
+                ProductDisplay {
+                    product: node.clone(),
+                    indent: 0,
+                    logistics: self.plan.logistics.as_ref(),
+                }
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for PlanDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let totals = DependencyResolutionTotalsDisplay {
+            totals: &self.plan.totals,
+            show_perfect_splits: self.show_perfect_splits,
+            show_recipes: self.plan.optimize,
+        };
+        let lines = || ProductionLinesDisplay(&self.plan.graph().merged()).to_string();
+        match self.section {
+            Some(PlanSection::Tree) => self.fmt_tree(f),
+            Some(PlanSection::Lines) => writeln!(f, "{}", lines()),
+            Some(PlanSection::Totals) => totals.fmt(f),
+            Some(PlanSection::Machines) => totals.fmt_machines(f),
+            None => {
+                writeln!(f)?;
+                for note in self.plan.notes() {
+                    writeln!(f, "Note: {}", note)?;
+                }
+                if !self.plan.notes().is_empty() {
+                    writeln!(f)?;
+                }
+                if self.merged {
+                    writeln!(f, "{}", lines())?;
+                } else {
+                    self.fmt_tree(f)?;
+                }
+                writeln!(f, "{}", totals)
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum GraphNodeKind {
+    /// A group of machines making one product with one recipe
+    Machine,
+    /// An item supplied from outside the factory
+    Input,
+    /// A product the factory was asked to make
+    Output,
+    /// Byproducts of an item, whether used elsewhere in the factory or left over
+    Byproduct,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum GraphEdgeKind {
+    /// Items made by a production line for its consumer; `*` in the tree
+    Production,
+    /// Items supplied from outside the factory; `-` in the tree
+    Supply,
+    /// Byproducts, going to or coming from the byproducts of an item; `<` & `>` in the tree
+    Byproduct,
+    /// Items fed back into an earlier production line; `~` in the tree
+    Cycle,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct GraphNode {
+    pub kind: GraphNodeKind,
+    /// The item the node makes, takes in or puts out
+    pub item: String,
+    /// The machines in the production line, for machine nodes
+    pub machine: Option<String>,
+    /// zero-based index of the recipe the production line uses, written out as its number from
+    /// `--list-recipes`
+    #[serde(serialize_with = "serialize_optional_recipe_number")]
+    pub recipe: Option<usize>,
+    pub machine_quantity: f32,
+    pub clock: f32,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct GraphEdge {
+    pub kind: GraphEdgeKind,
+    /// Index of the node the items come from
+    pub from: usize,
+    /// Index of the node the items go to
+    pub to: usize,
+    pub item: String,
+    pub quantity: f32,
+}
+
+/// A plan as a directed graph, with production lines, inputs & outputs as nodes and item flows as edges
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct ProductionGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+    /// nodes for inputs, outputs & byproducts are shared by every flow of the same item
+    #[serde(skip)]
+    shared_nodes: HashMap<(GraphNodeKind, String), usize>,
+}
+
+impl ProductionGraph {
+    fn from(dependency_trees: &[Product]) -> ProductionGraph {
+        let mut graph = ProductionGraph::default();
+        for product in dependency_trees {
+            let output = graph.shared_node(GraphNodeKind::Output, &product.name);
+            graph.add_product(product, output, &mut Vec::new());
+        }
+        graph
+    }
+
+    fn shared_node(&mut self, kind: GraphNodeKind, item: &str) -> usize {
+        if let Some(index) = self.shared_nodes.get(&(kind, item.to_string())) {
+            return *index;
+        }
+        self.nodes.push(GraphNode {
+            kind,
+            item: item.to_string(),
+            machine: None,
+            recipe: None,
+            machine_quantity: 0.0,
+            clock: 100.0,
+        });
+        self.shared_nodes
+            .insert((kind, item.to_string()), self.nodes.len() - 1);
+        self.nodes.len() - 1
+    }
+
+    fn add_edge(&mut self, kind: GraphEdgeKind, from: usize, to: usize, item: &str, quantity: f32) {
+        self.edges.push(GraphEdge {
+            kind,
+            from,
+            to,
+            item: item.to_string(),
+            quantity,
+        });
+    }
+
+    /// Add the production of `product` for the `consumer` node. `ancestors` holds the production
+    /// line making each item between the root of the tree and this product, to connect recipe loops
+    fn add_product(
+        &mut self,
+        product: &Product,
+        consumer: usize,
+        ancestors: &mut Vec<(String, usize)>,
+    ) {
+        for (quantity, source) in &product.sources {
+            match source {
+                Source::Recipe {
+                    machine,
+                    recipe,
+                    machine_quantity,
+                    clock,
+                    byproducts,
+                    ingredients,
+                    ..
+                } => {
+                    self.nodes.push(GraphNode {
+                        kind: GraphNodeKind::Machine,
+                        item: product.name.clone(),
+                        machine: Some(machine.clone()),
+                        recipe: Some(*recipe),
+                        machine_quantity: *machine_quantity,
+                        clock: *clock,
+                    });
+                    let node = self.nodes.len() - 1;
+                    // whatever is fed back into the line's own loops goes there instead of to the consumer
+                    let recycled = ingredients
+                        .iter()
+                        .map(|ingredient| ingredient.recycled(&product.name))
+                        .sum::<f32>();
+                    self.add_edge(
+                        GraphEdgeKind::Production,
+                        node,
+                        consumer,
+                        &product.name,
+                        *quantity - recycled,
+                    );
+                    for (byproduct, byproduct_quantity) in byproducts {
+                        let pool = self.shared_node(GraphNodeKind::Byproduct, byproduct);
+                        self.add_edge(
+                            GraphEdgeKind::Byproduct,
+                            node,
+                            pool,
+                            byproduct,
+                            *byproduct_quantity,
+                        );
+                    }
+                    ancestors.push((product.name.clone(), node));
+                    for ingredient in ingredients {
+                        self.add_product(ingredient, node, ancestors);
+                    }
+                    ancestors.pop();
+                }
+                Source::Supply => {
+                    let input = self.shared_node(GraphNodeKind::Input, &product.name);
+                    self.add_edge(
+                        GraphEdgeKind::Supply,
+                        input,
+                        consumer,
+                        &product.name,
+                        *quantity,
+                    );
+                }
+                Source::Byproduct => {
+                    let pool = self.shared_node(GraphNodeKind::Byproduct, &product.name);
+                    self.add_edge(
+                        GraphEdgeKind::Byproduct,
+                        pool,
+                        consumer,
+                        &product.name,
+                        *quantity,
+                    );
+                }
+                Source::Cycle => {
+                    if let Some((_, producer)) = ancestors
+                        .iter()
+                        .rev()
+                        .find(|(item, _)| *item == product.name)
+                    {
+                        self.add_edge(
+                            GraphEdgeKind::Cycle,
+                            *producer,
+                            consumer,
+                            &product.name,
+                            *quantity,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Merge every production line making the same product with the same recipe into one, along
+    /// with the flows between them. Merged lines run at the average of their clock speeds,
+    /// weighted by machine count
+    pub fn merged(&self) -> ProductionGraph {
+        let mut merged = ProductionGraph::default();
+        let mut lines: HashMap<(String, Option<usize>), usize> = HashMap::new();
+        let mut clock_totals: HashMap<usize, f32> = HashMap::new();
+        let mut node_map = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let index = match node.kind {
+                GraphNodeKind::Machine => {
+                    let index = *lines
+                        .entry((node.item.clone(), node.recipe))
+                        .or_insert_with(|| {
+                            merged.nodes.push(GraphNode {
+                                machine_quantity: 0.0,
+                                ..node.clone()
+                            });
+                            merged.nodes.len() - 1
+                        });
+                    merged.nodes[index].machine_quantity += node.machine_quantity;
+                    *clock_totals.get_default(&index) += node.machine_quantity * node.clock;
+                    index
+                }
+                kind => merged.shared_node(kind, &node.item),
+            };
+            node_map.push(index);
+        }
+        for (index, clock_total) in clock_totals {
+            let node = &mut merged.nodes[index];
+            if node.machine_quantity > FLOW_EPSILON as f32 {
+                node.clock = clock_total / node.machine_quantity;
+            }
+        }
+
+        let mut flows: HashMap<(GraphEdgeKind, usize, usize, String), usize> = HashMap::new();
+        for edge in &self.edges {
+            let key = (
+                edge.kind,
+                node_map[edge.from],
+                node_map[edge.to],
+                edge.item.clone(),
+            );
+            let (_, from, to, _) = key;
+            // a line feeding its own product back into itself is recycled flow, not a consumer
+            if from == to && edge.item == merged.nodes[from].item {
+                continue;
+            }
+            match flows.get(&key) {
+                Some(index) => merged.edges[*index].quantity += edge.quantity,
+                None => {
+                    flows.insert(key, merged.edges.len());
+                    merged.add_edge(edge.kind, from, to, &edge.item, edge.quantity);
+                }
+            }
+        }
+        merged
+    }
+
+    /// Show the graph in Graphviz DOT or Mermaid syntax
+    pub fn display(&self, format: GraphFormat) -> GraphDisplay<'_> {
+        GraphDisplay {
+            graph: self,
+            format,
+        }
+    }
+}
+
+impl GraphNode {
+    fn label(&self) -> Vec<String> {
+        match (self.kind, &self.machine) {
+            (GraphNodeKind::Machine, Some(machine)) => vec![
+                format!(
+                    "{:.2} {}{}",
+                    self.machine_quantity,
+                    machine,
+                    ClockDisplay(self.clock)
+                ),
+                self.item.clone(),
+            ],
+            (GraphNodeKind::Byproduct, _) => vec![format!("{} byproducts", self.item)],
+            _ => vec![self.item.clone()],
+        }
+    }
+}
+
+/// Shows every production line & supply in a graph, along with where their items go
+struct ProductionLinesDisplay<'a>(&'a ProductionGraph);
+
+impl ProductionLinesDisplay<'_> {
+    fn fmt_consumers(&self, f: &mut std::fmt::Formatter<'_>, node: usize) -> std::fmt::Result {
+        let graph = self.0;
+        let outflows = graph
+            .edges
+            .iter()
+            .filter(|edge| edge.from == node && edge.item == graph.nodes[node].item)
+            .collect::<Vec<_>>();
+        let total = outflows.iter().map(|edge| edge.quantity).sum::<f32>();
+        for edge in outflows {
+            let consumer = &graph.nodes[edge.to];
+            let destination = match (consumer.kind, &consumer.machine) {
+                (GraphNodeKind::Machine, Some(machine)) => {
+                    format!("{} in {}", consumer.item, machine)
+                }
+                (GraphNodeKind::Byproduct, _) => "byproducts".to_string(),
+                _ => "output".to_string(),
+            };
+            writeln!(
+                f,
+                "   -> {:.2} to {} ({:.2}%)",
+                edge.quantity,
+                destination,
+                edge.quantity / total * 100.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for ProductionLinesDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let graph = self.0;
+        let outflow = |node: usize| {
+            graph
+                .edges
+                .iter()
+                .filter(|edge| edge.from == node && edge.item == graph.nodes[node].item)
+                .map(|edge| edge.quantity)
+                .sum::<f32>()
+        };
+
+        writeln!(f, "Production Lines:")?;
+        for (index, node) in graph.nodes.iter().enumerate() {
+            let (GraphNodeKind::Machine, Some(machine)) = (node.kind, &node.machine) else {
+                continue;
+            };
+            writeln!(
+                f,
+                " * {:.2} {}: {:.2} {}{}",
+                outflow(index),
+                node.item,
+                node.machine_quantity,
+                machine,
+                ClockDisplay(node.clock)
+            )?;
+            self.fmt_consumers(f, index)?;
+            for edge in graph
+                .edges
+                .iter()
+                .filter(|edge| edge.from == index && edge.kind == GraphEdgeKind::Byproduct)
+            {
+                writeln!(f, "   < {:.2} {}", edge.quantity, edge.item)?;
+            }
+        }
+
+        for (heading, kind) in [
+            ("Supplies:", GraphNodeKind::Input),
+            ("Byproducts Used:", GraphNodeKind::Byproduct),
+        ] {
+            let supplies = graph
+                .nodes
+                .iter()
+                .enumerate()
+                .filter(|(index, node)| node.kind == kind && outflow(*index) > 0.0)
+                .collect::<Vec<_>>();
+            if supplies.is_empty() {
+                continue;
+            }
+            writeln!(f)?;
+            writeln!(f, "{heading}")?;
+            for (index, node) in supplies {
+                writeln!(f, " * {:.2} {}", outflow(index), node.item)?;
+                self.fmt_consumers(f, index)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GraphFormat {
+    Dot,
+    Mermaid,
+}
+
+pub struct GraphDisplay<'a> {
+    graph: &'a ProductionGraph,
+    format: GraphFormat,
+}
+
+impl GraphDisplay<'_> {
+    fn fmt_dot(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let escape = |text: &str| text.replace('\\', "\\\\").replace('"', "\\\"");
+        writeln!(f, "digraph factory {{")?;
+        writeln!(f, "    rankdir=LR;")?;
+        for (index, node) in self.graph.nodes.iter().enumerate() {
+            let shape = match node.kind {
+                GraphNodeKind::Machine => "box",
+                GraphNodeKind::Input => "invhouse",
+                GraphNodeKind::Output => "house",
+                GraphNodeKind::Byproduct => "ellipse",
+            };
+            let label = node
+                .label()
+                .iter()
+                .map(|line| escape(line))
+                .collect::<Vec<_>>()
+                .join("\\n");
+            writeln!(f, "    n{} [label=\"{}\", shape={}];", index, label, shape)?;
+        }
+        for edge in &self.graph.edges {
+            let style = match edge.kind {
+                GraphEdgeKind::Production => "solid",
+                GraphEdgeKind::Supply => "dashed",
+                GraphEdgeKind::Byproduct => "dotted",
+                GraphEdgeKind::Cycle => "dashed, constraint=false",
+            };
+            writeln!(
+                f,
+                "    n{} -> n{} [label=\"{:.2} {}\", style={}];",
+                edge.from,
+                edge.to,
+                edge.quantity,
+                escape(&edge.item),
+                style
+            )?;
+        }
+        writeln!(f, "}}")
+    }
+
+    fn fmt_mermaid(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let escape = |text: &str| text.replace('"', "#quot;");
+        writeln!(f, "flowchart LR")?;
+        for (index, node) in self.graph.nodes.iter().enumerate() {
+            let label = node
+                .label()
+                .iter()
+                .map(|line| escape(line))
+                .collect::<Vec<_>>()
+                .join("<br/>");
+            let (open, close) = match node.kind {
+                GraphNodeKind::Machine => ("[", "]"),
+                GraphNodeKind::Input => ("[/", "/]"),
+                GraphNodeKind::Output => ("[\\", "\\]"),
+                GraphNodeKind::Byproduct => ("([", "])"),
+            };
+            writeln!(f, "    n{}{}\"{}\"{}", index, open, label, close)?;
+        }
+        for edge in &self.graph.edges {
+            let arrow = match edge.kind {
+                GraphEdgeKind::Production => "-->",
+                GraphEdgeKind::Supply | GraphEdgeKind::Cycle => "-.->",
+                GraphEdgeKind::Byproduct => "==>",
+            };
+            writeln!(
+                f,
+                "    n{} {}|\"{:.2} {}\"| n{}",
+                edge.from,
+                arrow,
+                edge.quantity,
+                escape(&edge.item),
+                edge.to
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for GraphDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.format {
+            GraphFormat::Dot => self.fmt_dot(f),
+            GraphFormat::Mermaid => self.fmt_mermaid(f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A small set of recipes, numbered per product in the order they're listed
+    const RECIPES: &str = r#"[
+        {"name": "Iron Ingot", "machine": "Smelter", "ingredients": [["Iron Ore", 30]], "products": [["Iron Ingot", 30]]},
+        {"name": "Iron Plate", "machine": "Constructor", "ingredients": [["Iron Ingot", 30]], "products": [["Iron Plate", 20]]},
+        {"name": "Heavy Oil Residue", "machine": "Refinery", "ingredients": [["Crude Oil", 30]], "products": [["Heavy Oil Residue", 40]]},
+        {"name": "Plastic", "machine": "Refinery", "ingredients": [["Crude Oil", 30]], "products": [["Plastic", 20], ["Heavy Oil Residue", 10]]},
+        {"name": "Residual Fuel", "machine": "Refinery", "ingredients": [["Heavy Oil Residue", 60]], "products": [["Fuel", 40]]},
+        {"name": "Rubber", "machine": "Refinery", "ingredients": [["Crude Oil", 30]], "products": [["Rubber", 20]]},
+        {"name": "Recycled Rubber", "machine": "Refinery", "ingredients": [["Plastic", 30], ["Fuel", 30]], "products": [["Rubber", 60]]},
+        {"name": "Recycled Plastic", "machine": "Refinery", "ingredients": [["Rubber", 30], ["Fuel", 30]], "products": [["Plastic", 60]]},
+        {"name": "Unpackage Turbofuel", "machine": "Packager", "ingredients": [["Packaged Turbofuel", 20]], "products": [["Turbofuel", 20], ["Empty Canister", 20]]},
+        {"name": "Turbofuel", "machine": "Refinery", "ingredients": [["Fuel", 22.5], ["Compacted Coal", 15]], "products": [["Turbofuel", 18.75]]},
+        {"name": "Packaged Turbofuel", "machine": "Packager", "ingredients": [["Turbofuel", 20], ["Empty Canister", 20]], "products": [["Packaged Turbofuel", 20]]}
+    ]"#;
+
+    fn recipes(json: &str) -> Recipes {
+        let recipe_list: Vec<Recipe> = serde_json::from_str(json).unwrap();
+        let products = recipe_list
+            .iter()
+            .flat_map(|recipe| recipe.ingredients.iter().chain(&recipe.products))
+            .map(|(item, _)| item.clone())
+            .collect();
+        let recipes = recipe_list
+            .into_iter()
+            .flat_map(|recipe| {
+                recipe
+                    .products
+                    .clone()
+                    .into_iter()
+                    .map(move |(product, _)| (product, recipe.clone()))
+            })
+            .into();
+        Recipes {
+            recipes,
+            products,
+            machines: HashMap::new(),
+            fluids: HashSet::new(),
+            aliases: HashMap::new(),
+            abbreviations: false,
+        }
+    }
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() < 1e-3,
+            "expected {}, got {}",
+            expected,
+            actual
+        );
+    }
+
+    /// Every node of a tree draws some of its product from each of its sources
+    fn assert_no_empty_sources(product: &Product) {
+        for (quantity, source) in &product.sources {
+            assert!(
+                *quantity > 0.0,
+                "{} has an empty source: {:?}",
+                product.name,
+                source
+            );
+            if let Source::Recipe { ingredients, .. } = source {
+                ingredients.iter().for_each(assert_no_empty_sources);
+            }
+        }
+    }
+
+    fn planned(request: &mut PlanRequest) -> Plan {
+        let plan = request.plan(&recipes(RECIPES)).unwrap();
+        plan.trees.iter().for_each(assert_no_empty_sources);
+        plan
+    }
+
+    #[test]
+    fn requested_rates_are_met_exactly() {
+        let plan = planned(PlanRequest::new().want("iron plate", Some(40.0)));
+        assert_eq!(plan.totals.outputs["Iron Plate"], 40.0);
+        assert_eq!(plan.totals.inputs["Iron Ore"], 60.0);
+        assert_eq!(plan.totals.machines["Constructor"]["Iron Plate"], 2.0);
+        assert_eq!(plan.totals.machines["Smelter"]["Iron Ingot"], 2.0);
+    }
+
+    #[test]
+    fn unrequested_rates_default_to_one_machine() {
+        let plan = planned(PlanRequest::new().want("iron plate", None));
+        assert_eq!(plan.totals.outputs["Iron Plate"], 20.0);
+        assert_eq!(plan.totals.inputs["Iron Ore"], 30.0);
+    }
+
+    #[test]
+    fn limited_ingredients_scale_output() {
+        let plan = planned(
+            PlanRequest::new()
+                .want("iron plate", Some(40.0))
+                .have("iron ore", Some(30.0)),
+        );
+        assert_close(plan.totals.outputs["Iron Plate"], 20.0);
+        assert_close(plan.totals.inputs["Iron Ore"], 30.0);
+
+        let plan = planned(
+            PlanRequest::new()
+                .want("iron plate", Some(40.0))
+                .have("iron ore", Some(30.0))
+                .resupply_insufficient(true),
+        );
+        assert_close(plan.totals.outputs["Iron Plate"], 40.0);
+        assert_close(plan.totals.inputs["Iron Ore"], 60.0);
+    }
+
+    #[test]
+    fn byproducts_are_reused_when_asked() {
+        let wants = |request: &mut PlanRequest| {
+            request.want("plastic", Some(20.0)).want("fuel", Some(40.0));
+        };
+
+        let mut request = PlanRequest::new();
+        wants(&mut request);
+        let plan = planned(&mut request);
+        assert_close(plan.totals.inputs["Crude Oil"], 75.0);
+        assert_close(plan.totals.byproducts["Heavy Oil Residue"], 10.0);
+
+        let mut request = PlanRequest::new();
+        wants(&mut request);
+        let plan = planned(request.reuse_byproducts(true));
+        assert_close(plan.totals.inputs["Crude Oil"], 67.5);
+        assert_close(plan.totals.byproduct_inputs["Heavy Oil Residue"], 10.0);
+        assert_close(plan.totals.machines["Refinery"]["Heavy Oil Residue"], 1.25);
+    }
+
+    #[test]
+    fn recipe_loops_settle_into_a_steady_state() {
+        let plan = planned(
+            PlanRequest::new()
+                .want("rubber", Some(60.0))
+                .have("fuel", None)
+                .use_recipe("rubber", "2")
+                .use_recipe("plastic", "Recycled Plastic"),
+        );
+        assert_close(plan.totals.outputs["Rubber"], 60.0);
+        assert_close(plan.totals.inputs["Fuel"], 60.0);
+        assert_close(plan.totals.machines["Refinery"]["Rubber"], 4.0 / 3.0);
+        assert_close(plan.totals.machines["Refinery"]["Plastic"], 2.0 / 3.0);
+        // the rubber line makes 80, of which 20 goes back into making plastic
+        assert_close(plan.trees[0].recycled(&"Rubber".to_string()), 20.0);
+        assert!(plan.fallbacks.is_empty());
+    }
+
+    #[test]
+    fn loops_that_never_start_fall_back_to_the_next_recipe() {
+        let plan = planned(PlanRequest::new().want("turbofuel", Some(37.5)));
+        assert_eq!(plan.fallbacks.len(), 1);
+        let fallback = &plan.fallbacks[0];
+        assert_eq!(fallback.product, "Turbofuel");
+        assert_eq!((fallback.recipe, fallback.replacement), (0, 1));
+        assert_eq!(fallback.recipe_name, "Unpackage Turbofuel");
+        assert_eq!(
+            fallback.cycle,
+            ["Turbofuel", "Packaged Turbofuel", "Turbofuel"]
+        );
+        assert_close(plan.totals.outputs["Turbofuel"], 37.5);
+        assert_close(plan.totals.inputs["Compacted Coal"], 30.0);
+        assert!(!plan.totals.machines.contains_key("Packager"));
+    }
+
+    #[test]
+    fn loops_picked_explicitly_are_errors() {
+        let result = PlanRequest::new()
+            .want("turbofuel", Some(37.5))
+            .use_recipe("turbofuel", "1")
+            .plan(&recipes(RECIPES));
+        match result {
+            Err(Error::ClosedLoop { product, cycle }) => {
+                assert_eq!(product, "Turbofuel");
+                assert_eq!(cycle, ["Turbofuel", "Packaged Turbofuel", "Turbofuel"]);
+            }
+            other => panic!("expected a closed loop, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn loops_that_feed_back_everything_they_make_diverge() {
+        let recipes = recipes(
+            r#"[
+                {"machine": "Constructor", "ingredients": [["B", 10]], "products": [["A", 10]]},
+                {"machine": "Constructor", "ingredients": [["A", 10]], "products": [["B", 10]]}
+            ]"#,
+        );
+        let model = ProductionModel::new(
+            &recipes.recipes,
+            &recipes.machines,
+            &[("A".to_string(), Some(10.0))],
+            &HashMap::new(),
+            false,
+            false,
+            false,
+        );
+        let solution = ModelSolution {
+            machines: HashMap::from([(("A".to_string(), 0), 1.0), (("B".to_string(), 0), 1.0)]),
+            ..ModelSolution::default()
+        };
+        let result = model.expand(&solution, &"A".to_string(), 10.0, &mut Vec::new());
+        assert!(matches!(result, Err(Error::DivergentLoop(product)) if product == "A"));
+    }
+
+    /// Machine counts & clock speeds of each way to split a belt, by name
+    fn split_groups(machines: f32) -> Vec<(&'static str, Vec<(u32, f32)>)> {
+        perfect_splits(machines, 100.0)
+            .into_iter()
+            .map(|split| (split.name, split.groups))
+            .collect()
+    }
+
+    #[test]
+    fn splitter_trees_have_the_fewest_outputs() {
+        assert_eq!(splitter_tree(0), Vec::<u32>::new());
+        assert_eq!(splitter_tree(1), Vec::<u32>::new());
+        assert_eq!(splitter_tree(5), [2, 3]);
+        assert_eq!(splitter_tree(7), [2, 2, 2]);
+        assert_eq!(splitter_tree(11), [2, 2, 3]);
+        assert_eq!(splitter_tree(13), [2, 2, 2, 2]);
+    }
+
+    #[test]
+    fn no_machines_need_no_splits() {
+        assert!(perfect_splits(0.0, 100.0).is_empty());
+    }
+
+    #[test]
+    fn one_machine_needs_no_splitters() {
+        let splits = perfect_splits(1.0, 100.0);
+        assert_eq!(splits.len(), 1);
+        assert_eq!(splits[0].groups, [(1, 100.0)]);
+        assert_eq!(splits[0].topology, "no splitters");
+    }
+
+    #[test]
+    fn uneven_machine_counts_loop_back_unused_outputs() {
+        for (machines, outputs) in [(5, 6), (7, 8), (11, 12)] {
+            let splits = split_groups(machines as f32);
+            let work = machines as f32 * 100.0;
+            assert_eq!(
+                splits,
+                [
+                    ("splitter tree", vec![(outputs, work / outputs as f32)]),
+                    ("splitter tree with loopback", vec![(machines, 100.0)]),
+                    ("manifold", vec![(machines, 100.0)]),
+                ],
+                "{} machines",
+                machines
+            );
+        }
+        let loopback = &perfect_splits(5.0, 100.0)[1];
+        assert_eq!(
+            loopback.topology,
+            "1 2-way splitter -> 2 3-way splitters, with 1 output merged back into the input by 1 merger"
+        );
+    }
+
+    #[test]
+    fn two_groups_are_listed_when_they_need_fewer_machines() {
+        let splits = perfect_splits(13.0, 100.0);
+        let names: Vec<_> = splits.iter().map(|split| split.name).collect();
+        assert_eq!(
+            names,
+            [
+                "splitter tree",
+                "splitter tree with loopback",
+                "manifold",
+                "two groups"
+            ]
+        );
+        assert_eq!(splits[0].groups, [(16, 81.25)]);
+        assert!(splits[1]
+            .topology
+            .ends_with("3 outputs merged back into the input by 2 mergers"));
+
+        let two_groups = &splits[3];
+        assert_eq!(two_groups.groups.len(), 2);
+        assert_eq!(two_groups.groups[0].0, 6);
+        assert_close(two_groups.groups[0].1, 1300.0 / 3.0 / 6.0);
+        assert_eq!(two_groups.groups[1].0, 9);
+        assert_close(two_groups.groups[1].1, 2600.0 / 3.0 / 9.0);
+        assert_eq!(
+            two_groups.topology,
+            "1 3-way splitter with 2 outputs merged by 1 merger, then 1 2-way splitter -> 2 3-way \
+             splitters for the first group and 1 3-way splitter -> 3 3-way splitters for the second"
+        );
+    }
+
+    #[test]
+    fn malformed_recipe_entries_say_what_is_wrong() {
+        let error = |raw: &str| {
+            parse_config::<Vec<RecipeEntry>>("recipes.json", raw)
+                .unwrap_err()
+                .to_string()
+        };
+        let missing_products = error(r#"[{"machine": "Smelter", "ingredients": []}]"#);
+        assert!(
+            missing_products.contains("missing field `products`"),
+            "{}",
+            missing_products
+        );
+        let misspelled_remove = error(r#"[{"remove": "Iron Ingot", "machine": "Smelter"}]"#);
+        assert!(
+            misspelled_remove.contains("unknown field `machine`"),
+            "{}",
+            misspelled_remove
+        );
+
+        let entries = parse_config::<Vec<RecipeEntry>>(
+            "recipes.json",
+            r#"[{"remove": "Iron Ingot"}, {"machine": "Smelter", "ingredients": [], "products": []}]"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            entries.as_slice(),
+            [RecipeEntry::Remove { .. }, RecipeEntry::Recipe(_)]
+        ));
+    }
+
+    #[test]
+    fn misspelled_profile_keys_are_errors() {
+        let error =
+            parse_config::<Profile>("profile.json", r#"{"alternate": ["Pure Iron Ingot"]}"#)
+                .unwrap_err()
+                .to_string();
+        assert!(error.contains("unknown field `alternate`"), "{}", error);
+    }
+
+    #[test]
+    fn negative_plan_file_rates_are_errors() {
+        let error = parse_config::<PlanFile>(
+            "plan.json",
+            r#"{"want": [{"product": "iron plate", "rate": -3}]}"#,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(
+            error.contains("rate must be a number of at least 0, not -3"),
+            "{}",
+            error
+        );
+
+        let error = toml::from_str::<PlanFile>("want = [{ product = \"iron plate\", rate = -3 }]")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("rate must be a number of at least 0"),
+            "{}",
+            error
+        );
+
+        let plan_file = parse_config::<PlanFile>(
+            "plan.json",
+            r#"{"want": [{"product": "iron plate", "rate": 0}, {"product": "iron rod"}]}"#,
+        )
+        .unwrap();
+        assert_eq!(plan_file.want[0].rate, Some(0.0));
+        assert_eq!(plan_file.want[1].rate, None);
+    }
+
+    #[test]
+    fn partial_machines_only_need_shards_for_their_own_clock() {
+        assert_eq!(line_power_shards(0.0, 250.0), 0);
+        assert_eq!(line_power_shards(1.0, 200.0), 2);
+        // the half machine left over runs at 100%, so needs no shards
+        assert_eq!(line_power_shards(1.5, 200.0), 2);
+        assert_eq!(line_power_shards(1.8, 200.0), 4);
+        assert_eq!(line_power_shards(2.0, 250.0), 6);
+        assert_eq!(line_power_shards(0.6, 250.0), 1);
+        assert_eq!(line_power_shards(3.0, 100.0), 0);
+        assert_eq!(line_power_shards(0.5, 50.0), 0);
+    }
+
+    #[test]
+    fn unknown_recipe_names_list_the_products_recipes() {
+        let recipes = recipes(RECIPES);
+        assert_eq!(
+            recipes.select_recipe("Rubber", "recycled rubber").unwrap(),
+            1
+        );
+        assert_eq!(
+            recipes
+                .select_recipe("Rubber", "pure rubber")
+                .unwrap_err()
+                .to_string(),
+            "Rubber has no recipe named 'pure rubber'; its recipes are Rubber and Recycled Rubber"
+        );
+    }
+}
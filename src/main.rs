@@ -1,1072 +1,1116 @@
-use clap::{ArgAction, Parser};
-use regex::Regex;
-use serde::Deserialize;
+use clap::{ArgAction, CommandFactory, Parser, Subcommand, ValueEnum};
+use satisfactory_factory_planner::{
+    import_docs, parse_clock_speeds, parse_product_list, parse_recipe_selections,
+    parse_resource_nodes, write_config, BeltTier, Error, GraphFormat, MinerTier, PipeTier, Plan,
+    PlanFile, PlanRequest, PlanSection, ProductRate, Recipe, Recipes, RecipesLoader, POWER_SUFFIX,
+};
+use serde::Serialize;
 use std::{
-    collections::{HashMap, HashSet},
-    f32::consts::LN_2,
-    fmt::Display,
-    fs,
+    collections::BTreeMap,
+    fs::File,
+    io::{self, BufRead, BufReader, Write},
+    process,
 };
+use tiny_http::{Header, Method, Request, Response, Server};
 
-const BASIC_INGREDIENTS: [&str; 10] = [
-    "Coal",
-    "Limestone",
-    "Iron Ore",
-    "Copper Ore",
-    "Bauxite",
-    "Caterium Ore",
-    "Raw Quartz",
-    "Sulfur",
-    "Crude Oil",
-    "Water",
-];
-
-#[macro_export]
-macro_rules! debug {
-    ($val:expr) => {
-        #[cfg(debug_assertions)]
-        {
-            dbg!($val);
-        }
-    };
-}
+/// Everything that describes the factory to plan, for the default `make` command
+#[derive(clap::Args, Debug)]
+struct MakeArgs {
+    /// Product(s) to create, in the form `<name>[:rate][,<name>[:rate][...]]` etc.
+    #[arg(required_unless_present = "uses")]
+    want: Option<String>,
 
-trait DefaultDict<K, V>
-where
-    K: Eq + PartialEq + std::hash::Hash + Clone,
-    V: Default,
-{
-    fn get_default(&mut self, key: &K) -> &mut V;
-}
+    /// Ingredients that you have access to, in the form `<name>[:rate][,<name>[:rate][...]]` etc.
+    have: Option<String>,
 
-impl<K, V> DefaultDict<K, V> for HashMap<K, V>
-where
-    K: Eq + PartialEq + std::hash::Hash + Clone,
-    V: Default,
-{
-    fn get_default(&mut self, key: &K) -> &mut V {
-        self.entry(key.clone()).or_insert(V::default())
-    }
-}
+    /// List ways to evenly split a belt between the machines of each production line, with the
+    /// machine count & underclock for each, and the splitters & mergers needed to build it
+    #[arg(long, short = 'p', action = ArgAction::SetTrue)]
+    show_perfect_splits: bool,
 
-#[derive(Debug, Clone)]
-struct IndexedMap<K, V>
-where
-    K: std::hash::Hash + Eq + PartialEq,
-{
-    map: HashMap<K, Vec<V>>,
-    index: HashMap<K, usize>,
-}
+    /// If not enough input resources are available, then resupply more to fulfill the requested quota, instead of limiting the output totals
+    #[arg(long, short = 's', action = ArgAction::SetTrue)]
+    resupply_insufficient: bool,
 
-impl<K, V> IndexedMap<K, V>
-where
-    K: std::hash::Hash + Eq + PartialEq,
-{
-    fn new() -> IndexedMap<K, V> {
-        IndexedMap {
-            map: HashMap::new(),
-            index: HashMap::new(),
-        }
-    }
+    /// List all recipes that produce the given product
+    #[arg(long, short = 'l', action = ArgAction::SetTrue)]
+    list_recipes: bool,
 
-    fn get(&self, key: &K) -> Option<&V> {
-        self.map.get(key).map(|value_list| {
-            value_list
-                .get(
-                    *self
-                        .index
-                        .get(key)
-                        .unwrap_or(&0)
-                        .clamp(&0, &(value_list.len() - 1)),
-                )
-                .unwrap()
-        })
-    }
-}
+    /// List all recipes that consume the given item(s), and the product & number to choose each one
+    /// by with --recipes. Syntax is name[,name[,...]]
+    #[arg(long, conflicts_with_all = ["want", "list_recipes"])]
+    uses: Option<String>,
 
-impl<K, V, I> From<I> for IndexedMap<K, V>
-where
-    K: std::hash::Hash + Eq + PartialEq + Clone,
-    I: Iterator<Item = (K, V)>,
-{
-    fn from(value: I) -> Self {
-        let mut map = IndexedMap::new();
-        for (key, val) in value {
-            map.map.get_default(&key).push(val);
-        }
-        map
-    }
+    /// Provide overrides to existing recipes by passing a list of products and the associated recipe index or recipe name to use to manufacture said product.
+    /// Syntax is name:index|recipe name[,name:index|recipe name[,...]]
+    #[arg(long, short = 'r')]
+    recipes: Option<String>,
+
+    /// Allow the reuse of byproduct outputs from the system as inputs
+    #[arg(long, short = 'b', action = ArgAction::SetTrue)]
+    reuse_byproducts: bool,
+
+    /// Choose the combination of alternate recipes that uses the least raw resources, instead of the first recipe for each product.
+    /// Products given in --recipes keep the recipe chosen for them
+    #[arg(long, short = 'o', action = ArgAction::SetTrue)]
+    optimize: bool,
+
+    /// Clock speed to run machines at, as a percentage, either for all machines or per product.
+    /// Syntax is clock[,name:clock[,...]]
+    #[arg(long, short = 'k')]
+    clock: Option<String>,
+
+    /// Plan the miners & extractors needed to supply raw resources
+    #[arg(long, short = 'e', action = ArgAction::SetTrue)]
+    extraction: bool,
+
+    /// Resource nodes available for extraction; implies --extraction.
+    /// Syntax is resource: count purity[, count purity[, ...]][, resource: ...]
+    #[arg(long, short = 'n')]
+    nodes: Option<String>,
+
+    /// Miner tier to use for extraction
+    #[arg(long, value_enum, default_value = "mk1")]
+    miner: MinerTier,
+
+    /// Show the conveyor belts & pipes needed to carry each item in the tree, and flag machines
+    /// that need more than one belt or pipe for a single input or output
+    #[arg(long, short = 't', action = ArgAction::SetTrue)]
+    throughput: bool,
+
+    /// Fastest conveyor belt available; machines are slowed down to never need more than one of them
+    /// for a single input or output. Implies --throughput
+    #[arg(long, value_enum)]
+    max_belt: Option<BeltTier>,
+
+    /// Fastest pipeline available; machines are slowed down to never need more than one of them
+    /// for a single input or output. Implies --throughput
+    #[arg(long, value_enum)]
+    max_pipe: Option<PipeTier>,
 }
 
-#[derive(Deserialize, Clone)]
-struct Recipe {
-    machine: String,
-    ingredients: Vec<(String, f32)>,
-    products: Vec<(String, f32)>,
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Plan a factory that makes the wanted products. This is the default command, so `make` can
+    /// be left out
+    Make(MakeArgs),
+    /// Import recipes, machine power usage and item forms & stack sizes from the game's Docs.json,
+    /// overwriting the recipe, machine & item configs
+    ImportDocs {
+        /// Path to Docs.json, found in the game's CommunityResources/Docs folder
+        docs: String,
+    },
+    /// Plan a factory one change at a time, loading recipes only once. Type `help` for a list of
+    /// commands
+    Interactive,
+    /// Plan every request in a JSON Lines file, printing one JSON result per line, followed by a
+    /// summary of the requests that failed
+    Batch {
+        /// Path to the requests, each a plan file in JSON on a single line; read from stdin if not
+        /// given
+        file: Option<String>,
+    },
+    /// List every product that can be made from the given ingredients alone, and the most of each
+    /// that can be made
+    Buildable {
+        /// Ingredients that you have access to, in the form `<name>[:rate][,<name>[:rate][...]]` etc.
+        have: String,
+
+        /// Recipe overrides, in the same syntax as for planning
+        #[arg(long, short = 'r')]
+        recipes: Option<String>,
+
+        /// Allow the reuse of byproduct outputs from the system as inputs
+        #[arg(long, short = 'b', action = ArgAction::SetTrue)]
+        reuse_byproducts: bool,
+
+        /// Choose the combination of alternate recipes that makes the most of each product
+        #[arg(long, short = 'o', action = ArgAction::SetTrue)]
+        optimize: bool,
+    },
+    /// Serve a JSON API over HTTP, keeping recipes loaded between requests. Endpoints are
+    /// POST /plan, GET /recipes/{product} and GET /products
+    Serve {
+        /// Port to listen on
+        #[arg(long, default_value = "8080")]
+        port: u16,
+
+        /// Address to listen on; only local connections are accepted by default
+        #[arg(long, default_value = "127.0.0.1")]
+        host: String,
+    },
+    /// Save a plan to a TOML or JSON file, or plan the factory kept in one
+    Plan {
+        #[command(subcommand)]
+        command: PlanCommand,
+    },
 }
 
-#[derive(Clone, Debug)]
-enum Source {
-    Recipe {
-        machine: String,
-        machine_quantity: f32,
-        byproducts: Vec<(String, f32)>,
-        ingredients: Vec<Product>,
+#[derive(Subcommand, Debug)]
+enum PlanCommand {
+    /// Plan & display the factory kept in a plan file
+    Run {
+        /// Path to the plan file, ending in .toml or .json
+        file: String,
+    },
+    /// Save a plan to a file, overwriting it, to be planned again with `plan run`
+    Save {
+        /// Path to the plan file, ending in .toml or .json
+        file: String,
+
+        /// Product(s) to create, in the form `<name>[:rate][,<name>[:rate][...]]` etc.
+        want: String,
+
+        /// Ingredients that you have access to, in the form `<name>[:rate][,<name>[:rate][...]]` etc.
+        have: Option<String>,
+
+        /// Recipe overrides, in the same syntax as for planning
+        #[arg(long, short = 'r')]
+        recipes: Option<String>,
+
+        /// List ways to evenly split a belt between the machines of each production line, with the
+        /// machine count & underclock for each, and the splitters & mergers needed to build it
+        #[arg(long, short = 'p', action = ArgAction::SetTrue)]
+        show_perfect_splits: bool,
+
+        /// If not enough input resources are available, then resupply more to fulfill the requested quota
+        #[arg(long, short = 's', action = ArgAction::SetTrue)]
+        resupply_insufficient: bool,
+
+        /// Allow the reuse of byproduct outputs from the system as inputs
+        #[arg(long, short = 'b', action = ArgAction::SetTrue)]
+        reuse_byproducts: bool,
     },
-    Supply,
-    Byproduct,
 }
 
-#[derive(Clone, Debug)]
-struct Product {
-    name: String,
-    unsupplied: f32,
-    sources: Vec<(f32, Source)>,
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum OutputFormat {
+    /// The plan as a tree, followed by its totals
+    Text,
+    /// The full tree & totals as JSON
+    Json,
+    /// The production graph in Graphviz DOT syntax
+    Dot,
+    /// The production graph as a Mermaid flowchart
+    Mermaid,
 }
 
-impl Product {
-    fn adjust_quantities(&mut self, adjustment: f32) {
-        self.unsupplied *= adjustment;
-        for (ref mut quantity, ref mut source) in self.sources.iter_mut() {
-            *quantity *= adjustment;
-            match source {
-                Source::Recipe {
-                    machine_quantity,
-                    ingredients,
-                    byproducts,
-                    ..
-                } => {
-                    *machine_quantity *= adjustment;
-                    ingredients
-                        .iter_mut()
-                        .for_each(|ingredient| ingredient.adjust_quantities(adjustment));
-                    byproducts
-                        .iter_mut()
-                        .for_each(|(_, byproduct_quantity)| *byproduct_quantity *= adjustment);
-                }
-                _ => (),
+/// Satisfactory Factory Planning Utility
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+
+    /// Specify custom config files for crafting recipes. May be given more than once, with each file
+    /// adding, replacing or removing recipes from the files before it
+    #[arg(long, short = 'c', default_value = "recipes.json", global = true, action = ArgAction::Append)]
+    recipe_config: Vec<String>,
+
+    /// Specify a custom config file for machine power usage; machines draw no power if the file
+    /// doesn't exist
+    #[arg(long, short = 'm', default_value = "machines.json", global = true)]
+    machine_config: String,
+
+    /// Specify a custom config file for item forms & stack sizes; solid & fluid items are told apart
+    /// with a built-in list if the file doesn't exist
+    #[arg(long, default_value = "items.json", global = true)]
+    item_config: String,
+
+    /// Specify a profile of unlocked milestones, MAM research & alternate recipes; only unlocked
+    /// recipes are used. Milestones & research only apply to recipes imported with import-docs,
+    /// as the included recipes don't say what unlocks them. Every recipe is used if the file
+    /// doesn't exist
+    #[arg(long, default_value = "profile.json", global = true)]
+    profile: String,
+
+    /// Specify a config file of alternative names for products, as an object of aliases & the
+    /// products they stand for. No aliases are used if the file doesn't exist
+    #[arg(long, default_value = "aliases.json", global = true)]
+    alias_config: String,
+
+    /// Accept the initials of a product's name in place of the full name, e.g. hmf for Heavy
+    /// Modular Frame
+    #[arg(long, short = 'a', action = ArgAction::SetTrue, global = true)]
+    abbreviations: bool,
+
+    /// Specify a custom config file for power generators; no power can be planned if the file
+    /// doesn't exist
+    #[arg(long, short = 'g', default_value = "generators.json", global = true)]
+    generator_config: String,
+
+    /// Merge every production line making the same product with the same recipe into one, and show
+    /// where each line's products go, instead of a tree with repeated subtrees
+    #[arg(long, action = ArgAction::SetTrue, global = true)]
+    merge: bool,
+
+    /// Format to print the plan, or the recipes given by --list-recipes, in
+    #[arg(long, value_enum, default_value = "text", global = true)]
+    format: OutputFormat,
+}
+
+/// Put the default `make` command in front of the arguments, unless they already start with a
+/// command, or only ask for help or the version
+fn with_default_command(args: Vec<String>) -> Vec<String> {
+    let command = Args::command();
+    let options: Vec<_> = command
+        .get_arguments()
+        .chain(
+            command
+                .get_subcommands()
+                .flat_map(|sub| sub.get_arguments()),
+        )
+        .filter(|arg| !arg.is_positional())
+        .collect();
+    let takes_value = |matches: &dyn Fn(&clap::Arg) -> bool| {
+        options
+            .iter()
+            .any(|arg| matches(arg) && arg.get_action().takes_values())
+    };
+
+    // find the first argument that isn't an option or an option's value
+    let mut index = 1;
+    let first_positional = loop {
+        let Some(token) = args.get(index) else {
+            break None;
+        };
+        if token == "--" {
+            break args.get(index + 1);
+        }
+        if let Some(long) = token.strip_prefix("--") {
+            if !long.contains('=') && takes_value(&|arg| arg.get_long() == Some(long)) {
+                index += 1;
             }
+        } else if let Some(shorts) = token.strip_prefix('-').filter(|shorts| !shorts.is_empty()) {
+            // a value is either the rest of a group of short options, or the next argument
+            let value_at = shorts
+                .chars()
+                .position(|short| takes_value(&|arg| arg.get_short() == Some(short)));
+            if value_at == Some(shorts.chars().count() - 1) {
+                index += 1;
+            }
+        } else {
+            break Some(token);
         }
-    }
-}
+        index += 1;
+    };
 
-impl Display for Product {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        ProductDisplay {
-            product: self.clone(),
-            indent: 0,
+    let is_command = |name: &str| {
+        name == "help"
+            || command.get_subcommands().any(|sub| {
+                sub.get_name() == name || sub.get_all_aliases().any(|alias| alias == name)
+            })
+    };
+    let asks_for_help = || {
+        args.iter()
+            .any(|arg| ["-h", "--help", "-V", "--version"].contains(&arg.as_str()))
+    };
+    match first_positional {
+        Some(name) if is_command(name) => args,
+        None if asks_for_help() => args,
+        _ => {
+            let mut args = args;
+            args.insert(1.min(args.len()), "make".to_string());
+            args
         }
-        .fmt(f)
     }
 }
 
-struct ProductDisplay {
-    product: Product,
-    indent: usize,
-}
-
-impl Display for ProductDisplay {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for (quantity, source) in &self.product.sources {
-            match source {
-                Source::Recipe {
-                    machine,
-                    machine_quantity,
-                    ingredients,
-                    byproducts,
-                } => {
-                    writeln!(
-                        f,
-                        "{:>indent$} * {:.2} {}: {:.2} {}",
-                        "",
-                        quantity,
-                        self.product.name,
-                        machine_quantity,
-                        machine,
-                        indent = self.indent,
-                    )?;
-                    for sub_product in ingredients.iter() {
-                        ProductDisplay {
-                            product: sub_product.clone(),
-                            indent: self.indent + 2,
-                        }
-                        .fmt(f)?;
-                    }
-                    for (byproduct, byproduct_quantity) in byproducts.iter() {
-                        writeln!(
-                            f,
-                            "{:>indent$} < {:.2} {}",
-                            "",
-                            byproduct_quantity,
-                            byproduct,
-                            indent = self.indent,
-                        )?;
-                    }
-                }
-                Source::Supply => {
-                    writeln!(
-                        f,
-                        "{:>indent$} - {:.2} {}",
-                        "",
-                        quantity,
-                        self.product.name,
-                        indent = self.indent,
-                    )?;
-                }
-                Source::Byproduct => {
-                    writeln!(
-                        f,
-                        "{:>indent$} > {:.2} {}",
-                        "",
-                        quantity,
-                        self.product.name,
-                        indent = self.indent,
-                    )?;
-                }
-            }
-        }
-        Ok(())
+fn main() {
+    // parse arguments
+    #[cfg(not(debug_assertions))]
+    let args = Args::parse_from(with_default_command(std::env::args().collect()));
+    #[cfg(debug_assertions)]
+    let args = Args::parse_from(with_default_command(vec![
+        "_".to_string(),
+        "gas filter".to_string(),
+    ]));
+
+    if let Err(err) = run(args) {
+        eprintln!("Error: {}", err);
+        process::exit(1);
     }
 }
 
-#[derive(Debug)]
-struct DependencyResolutionTotals {
-    inputs: HashMap<String, f32>,
-    byproduct_inputs: HashMap<String, f32>,
-    intermediate_ingredients: HashMap<String, f32>,
-    outputs: HashMap<String, f32>,
-    byproducts: HashMap<String, f32>,
-    machines: HashMap<String, HashMap<String, f32>>,
+/// Look up a product's full name, pointing out if a misspelled name was corrected
+fn product_name(recipes: &Recipes, name: &str) -> Result<String, Error> {
+    let found = recipes.find_product(name)?;
+    if found.corrected {
+        eprintln!("Note: using '{}' for '{}'", found.name, name.trim());
+    }
+    Ok(found.name)
 }
 
-impl DependencyResolutionTotals {
-    fn new() -> DependencyResolutionTotals {
-        DependencyResolutionTotals {
-            inputs: HashMap::new(),
-            byproduct_inputs: HashMap::new(),
-            intermediate_ingredients: HashMap::new(),
-            outputs: HashMap::new(),
-            byproducts: HashMap::new(),
-            machines: HashMap::new(),
+/// Print every recipe that produces a product, numbered for use with --recipes
+fn print_recipes(recipes: &Recipes, product: &str) -> Result<(), Error> {
+    let product = product_name(recipes, product)?;
+    println!("{}:", product);
+    let recipe_set = recipes.recipes_for(&product);
+    if recipe_set.is_empty() {
+        println!(" * No recipes for this product.");
+    }
+    for (i, recipe) in recipe_set.iter().enumerate() {
+        match &recipe.name {
+            Some(name) => println!(" {}. {} (from {})", i + 1, name, recipe.config),
+            None => println!(" {}. (from {})", i + 1, recipe.config),
         }
+        print_recipe_items(recipe);
+        println!();
     }
+    Ok(())
+}
 
-    fn from(dependency_trees: &Vec<Product>) -> DependencyResolutionTotals {
-        let mut totals = DependencyResolutionTotals::new();
-        totals.tally_trees(dependency_trees);
-        totals
+/// Print every recipe that consumes an item, along with the product & number to choose it by
+fn print_uses(recipes: &Recipes, item: &str) -> Result<(), Error> {
+    let item = product_name(recipes, item)?;
+    println!("{} is used by:", item);
+    let uses = recipes.recipes_using(&item);
+    if uses.is_empty() {
+        println!(" * No recipes use this item.");
     }
+    for (product, index, recipe) in uses {
+        println!(
+            " * {} in {} ({} recipe {}, from {})",
+            recipe.name.as_deref().unwrap_or(product),
+            recipe.machine,
+            product,
+            index + 1,
+            recipe.config
+        );
+        print_recipe_items(recipe);
+        println!();
+    }
+    Ok(())
+}
 
-    fn tally_trees(&mut self, dependency_trees: &Vec<Product>) {
-        dependency_trees.iter().for_each(|product| {
-            // tally outputs
-            for (quantity, _) in &product.sources {
-                *self.outputs.get_default(&product.name) += quantity;
-            }
-
-            // tally sub-nodes
-            self.tally_node(product);
-        })
+/// Print every product that can be made from the ingredients a request has
+fn print_buildable(args: &Args, recipes: &Recipes, request: &PlanRequest) -> Result<(), Error> {
+    let buildable = request.buildable(recipes)?;
+    if args.format == OutputFormat::Json {
+        let buildable: BTreeMap<_, _> = buildable.into_iter().collect();
+        println!("{}", serde_json::to_string_pretty(&buildable).unwrap());
+        return Ok(());
     }
 
-    fn tally_node(&mut self, node: &Product) {
-        for (_, source) in &node.sources {
-            match source {
-                Source::Recipe {
-                    machine,
-                    machine_quantity,
-                    ingredients,
-                    byproducts,
-                } => {
-                    // tally machine counts
-                    *self.machines.get_default(&machine).get_default(&node.name) +=
-                        machine_quantity;
-
-                    // tally byproducts
-                    byproducts.iter().for_each(|(product, quantity)| {
-                        *self.byproducts.get_default(&product) += quantity
-                    });
-
-                    // tally intermediate ingredients, inputs, and sub-nodes
-                    ingredients.iter().for_each(|product| {
-                        for (quantity, sub_source) in &product.sources {
-                            match sub_source {
-                                Source::Recipe { .. } => {
-                                    *self.intermediate_ingredients.get_default(&product.name) +=
-                                        quantity;
-                                    self.tally_node(product);
-                                }
-                                Source::Supply => {
-                                    *self.inputs.get_default(&product.name) += quantity;
-                                }
-                                Source::Byproduct => {
-                                    *self.byproduct_inputs.get_default(&product.name) += quantity;
-                                }
-                            }
-                        }
-                    });
-                }
-                _ => (),
+    println!("Buildable Products:");
+    if buildable.is_empty() {
+        println!(" * Nothing can be made from these ingredients alone.");
+    }
+    for (product, rate) in buildable {
+        match rate {
+            Some(rate) if product.ends_with(POWER_SUFFIX) => {
+                println!(" * {:.2} MW {}", rate, product)
             }
+            Some(rate) => println!(" * {:.2} {}/min", rate, product),
+            None => println!(" * {}, without limit", product),
         }
     }
+    Ok(())
 }
 
-impl Display for DependencyResolutionTotals {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        DependencyResolutionTotalsDisplay {
-            totals: self,
-            show_perfect_splits: false,
+/// Print the rates a recipe consumes & produces items at
+fn print_recipe_items(recipe: &Recipe) {
+    println!("    Ingredients:");
+    for (ingredient, quantity) in recipe.ingredients.iter() {
+        println!("     - {:.2} {}/min", quantity, ingredient);
+    }
+    println!("    Products:");
+    for (product, quantity) in recipe.products.iter() {
+        if product.ends_with(POWER_SUFFIX) {
+            println!("     - {:.2} MW {}", quantity, product);
+        } else {
+            println!("     - {:.2} {}/min", quantity, product);
         }
-        .fmt(f)
     }
 }
 
-struct DependencyResolutionTotalsDisplay<'a> {
-    totals: &'a DependencyResolutionTotals,
-    show_perfect_splits: bool,
+/// A recipe that consumes an item, as listed by --uses with --format json
+#[derive(Serialize)]
+struct RecipeUse<'a> {
+    product: &'a str,
+    recipe: usize,
+    #[serde(flatten)]
+    details: &'a Recipe,
 }
 
-impl Display for DependencyResolutionTotalsDisplay<'_> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let unused_byproducts = self
-            .totals
-            .byproducts
-            .iter()
-            .filter_map(|(byproduct, quantity_produced)| {
-                let quantity_used = self.totals.byproduct_inputs.get(byproduct).unwrap_or(&0.0);
-                if quantity_used >= quantity_produced {
-                    None
-                } else {
-                    Some((byproduct.clone(), quantity_produced - quantity_used))
-                }
-            })
-            .collect();
+const INTERACTIVE_HELP: &str = "\
+Commands:
+  want <name>[:rate][,...]    Make products, replacing any rates given for them before
+  have <name>[:rate][,...]    Supply ingredients, replacing any rates given for them before
+  use <name>:<recipe>[,...]   Make products with a recipe, given by its number or name
+  drop <name>[,...]           Stop making, supplying or choosing a recipe for products
+  set <option> on|off         Turn optimize, reuse-byproducts, resupply-insufficient or
+                              perfect-splits on or off
+  show [<part>]               Show the plan, or just its tree, lines, totals or machines
+  list <name>                 List the recipes for a product
+  uses <name>                 List the recipes that consume an item
+  undo                        Undo the last change
+  help                        Show this message
+  quit                        Leave";
+
+/// Everything set up in an interactive session, kept for every change so that it can be undone
+#[derive(Clone, Default)]
+struct Session {
+    request: PlanRequest,
+    show_perfect_splits: bool,
+}
 
-        for (heading, product_list) in vec![
-            ("Input Ingredients:", &self.totals.inputs),
-            (
-                "Intermediate Ingredients:",
-                &self.totals.intermediate_ingredients,
-            ),
-            ("Output Products:", &self.totals.outputs),
-            ("Byproducts:", &unused_byproducts),
-        ] {
-            if !product_list.is_empty() {
-                writeln!(f, "{heading}")?;
-                for (product, quantity) in product_list.iter() {
-                    writeln!(f, " * {:.2} {}", quantity, product)?;
-                }
+/// What an interactive command did
+enum Outcome {
+    Changed(Session),
+    Undo,
+    Quit,
+    Done,
+}
 
-                writeln!(f)?;
-            }
+/// Plan a factory interactively, reading commands from stdin until it's closed or `quit` is typed
+fn interactive(recipes: &Recipes) -> Result<(), Error> {
+    println!("Type `help` for a list of commands.");
+    let mut history = vec![Session::default()];
+    let mut line = String::new();
+    loop {
+        print!("> ");
+        io::stdout().flush().ok();
+        line.clear();
+        let read = io::stdin()
+            .read_line(&mut line)
+            .map_err(|source| Error::ReadFailed {
+                file: "stdin".to_string(),
+                source,
+            })?;
+        if read == 0 {
+            println!();
+            return Ok(());
         }
 
-        if !self.totals.byproducts.is_empty() {}
-
-        writeln!(f, "Machines:")?;
-        for (machine, machine_products) in self.totals.machines.iter() {
-            writeln!(f, " * {}", machine)?;
-            for (product, quantity) in machine_products.iter() {
-                if self.show_perfect_splits {
-                    let round_up = quantity.ceil() as u32;
-                    let (splitters_2, splitters_3, perfect_split_quantity) =
-                        nearest_perfect_split(round_up).unwrap();
-                    writeln!(
-                        f,
-                        "   - {:.2} for {}s, or 2^{} * 3^{} = {} at {:.2}%",
-                        quantity,
-                        product,
-                        splitters_2,
-                        splitters_3,
-                        perfect_split_quantity,
-                        (quantity / perfect_split_quantity as f32) * 100.0
-                    )?;
+        let session = history.last().unwrap();
+        match interactive_command(recipes, session, line.trim()) {
+            Ok(Outcome::Changed(session)) => {
+                // only keep changes that can be planned
+                if let Err(err) = print_summary(recipes, &session) {
+                    println!("Error: {}", err);
                 } else {
-                    writeln!(f, "   - {:.2} for {}s", quantity, product)?;
+                    history.push(session);
                 }
             }
+            Ok(Outcome::Undo) if history.len() > 1 => {
+                history.pop();
+                print_summary(recipes, history.last().unwrap())?;
+            }
+            Ok(Outcome::Undo) => println!("Nothing to undo."),
+            Ok(Outcome::Quit) => return Ok(()),
+            Ok(Outcome::Done) => (),
+            Err(err) => println!("Error: {}", err),
         }
-
-        Ok(())
     }
 }
 
-const LN_3: f32 = 1.0986122886681098f32;
-const FRAC_LN_2_LN_3: f32 = LN_2 / LN_3;
-fn uceil(x: f32) -> u32 {
-    x.ceil() as u32
-}
-
-/// this algorithm is an unholy abomination
-fn nearest_perfect_split(base_machine_count: u32) -> Option<(u32, u32, u32)> {
-    let ln_c: f32 = (base_machine_count as f32).ln();
-    let b: f32 = ln_c / LN_3;
-    let f = |x: u32| uceil((-FRAC_LN_2_LN_3 * (x as f32) + b).max(0.0));
-    let mut result: Option<(u32, u32, u32)> = None;
-    let mut closest_dist: Option<u32> = None;
-    let mut x_pow2: u32 = 1;
-    let mut last_y: u32 = f(0);
-    let mut y_pow3: u32 = 3u32.pow(last_y);
-    for x in 0..=uceil(ln_c / LN_2) {
-        let y: u32 = f(x);
-        if y != last_y {
-            if y == last_y - 1 {
-                y_pow3 /= 3;
-            } else {
-                y_pow3 = 3u32.pow(y);
+/// Carry out a single interactive command
+fn interactive_command(recipes: &Recipes, session: &Session, line: &str) -> Result<Outcome, Error> {
+    let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+    let argument = argument.trim();
+    let mut session = session.clone();
+    match command.to_lowercase().as_str() {
+        "" => return Ok(Outcome::Done),
+        // names are stored in full, so that the same product given another way replaces it
+        "want" => {
+            for (product, rate) in parse_product_list(argument)? {
+                let product = product_name(recipes, &product)?;
+                session.request.remove_want(&product);
+                session.request.want(product, rate);
             }
-            last_y = y;
         }
-        let split_value: u32 = x_pow2 * y_pow3;
-        let new_dist: u32 = split_value - base_machine_count;
-        if closest_dist.map_or(true, |c_dist| new_dist < c_dist) {
-            result = Some((x, y, split_value));
-            if new_dist == 0 {
-                break;
+        "have" => {
+            for (ingredient, rate) in parse_product_list(argument)? {
+                let ingredient = product_name(recipes, &ingredient)?;
+                session.request.remove_have(&ingredient);
+                session.request.have(ingredient, rate);
             }
-            closest_dist = Some(new_dist);
         }
-        x_pow2 <<= 1;
-    }
-    result
-}
-
-fn resolve_product_dependencies(
-    recipes: &IndexedMap<String, Recipe>,
-    product: &mut Product,
-    available_ingredients: &Vec<String>,
-    available_byproducts: &HashMap<String, f32>,
-) {
-    // iterate down the tree to hit every node
-    for (_, source) in product.sources.iter_mut() {
-        match source {
-            Source::Recipe {
-                ingredients,
-                byproducts,
-                ..
-            } => {
-                let byproducts_map = byproducts.clone().into_iter().collect::<HashMap<_, _>>();
-                for ingredient in ingredients.iter_mut() {
-                    resolve_product_dependencies(
-                        recipes,
-                        ingredient,
-                        available_ingredients,
-                        &available_byproducts
-                            .iter()
-                            .map(|(byproduct, quantity)| {
-                                (
-                                    byproduct.clone(),
-                                    quantity - byproducts_map.get(byproduct).unwrap_or(&0.0),
-                                )
-                            })
-                            .collect(),
-                    )
+        "use" => {
+            for (product, recipe) in parse_recipe_selections(argument)? {
+                let product = product_name(recipes, &product)?;
+                session.request.remove_recipe(&product);
+                session.request.use_recipe(product, recipe);
+            }
+        }
+        "drop" => {
+            for (product, _) in parse_product_list(argument)? {
+                let product = product_name(recipes, &product)?;
+                let removed = [
+                    session.request.remove_want(&product),
+                    session.request.remove_have(&product),
+                    session.request.remove_recipe(&product),
+                ];
+                if !removed.contains(&true) {
+                    return Err(Error::NotRequested(product));
                 }
             }
-            _ => (),
         }
-    }
-
-    // cater to unsupplied required resources
-    if product.unsupplied > 0.0 {
-        if BASIC_INGREDIENTS.contains(&product.name.as_str())
-            || available_ingredients.contains(&product.name)
-        {
-            product.sources.push((product.unsupplied, Source::Supply));
-        } else if available_byproducts
-            .get(&product.name)
-            .map_or(false, |quantity| *quantity > 0.0)
-        {
-            product
-                .sources
-                .push((product.unsupplied, Source::Byproduct));
-        } else {
-            match recipes.get(&product.name) {
-                None => product.sources.push((product.unsupplied, Source::Supply)),
-                Some(recipe) => {
-                    if product.unsupplied > 0.0 {
-                        // determine production ratio
-                        let production_ratio = product.unsupplied
-                            / recipe
-                                .products
-                                .iter()
-                                .find(|(recipe_product, _)| *recipe_product == product.name)
-                                .expect("Recipe in value missing product from its key?!")
-                                .1;
-
-                        // compute ingredient dependencies
-                        let byproducts = recipe
-                            .products
-                            .iter()
-                            .filter_map(|(recipe_product, quantity)| {
-                                if *recipe_product != product.name {
-                                    Some((recipe_product.clone(), quantity * production_ratio))
-                                } else {
-                                    None
-                                }
-                            })
-                            .collect::<Vec<(String, f32)>>();
-                        let byproducts_map =
-                            byproducts.clone().into_iter().collect::<HashMap<_, _>>();
-
-                        product.sources.push((
-                            product.unsupplied,
-                            Source::Recipe {
-                                machine: recipe.machine.clone(),
-                                machine_quantity: production_ratio,
-
-                                byproducts,
-                                ingredients: recipe
-                                    .ingredients
-                                    .iter()
-                                    .map(|(recipe_product, quantity)| {
-                                        let mut inner_product = Product {
-                                            name: recipe_product.clone(),
-                                            unsupplied: quantity * production_ratio,
-                                            sources: Vec::new(),
-                                        };
-                                        resolve_product_dependencies(
-                                            recipes,
-                                            &mut inner_product,
-                                            available_ingredients,
-                                            &available_byproducts
-                                                .iter()
-                                                .map(|(byproduct, quantity)| {
-                                                    (
-                                                        byproduct.clone(),
-                                                        quantity
-                                                            - byproducts_map
-                                                                .get(byproduct)
-                                                                .unwrap_or(&0.0),
-                                                    )
-                                                })
-                                                .collect(),
-                                        );
-                                        inner_product
-                                    })
-                                    .collect(),
-                            },
-                        ));
-                    }
+        "set" => {
+            let (option, value) = argument.split_once(' ').unwrap_or((argument, ""));
+            let value = match value.trim().to_lowercase().as_str() {
+                "on" => true,
+                "off" => false,
+                _ => {
+                    return Err(Error::InvalidSyntax {
+                        input: line.to_string(),
+                        expected: "set <option> on|off",
+                    })
                 }
             };
+            match option.to_lowercase().as_str() {
+                "optimize" => {
+                    session.request.optimize(value);
+                }
+                "reuse-byproducts" => {
+                    session.request.reuse_byproducts(value);
+                }
+                "resupply-insufficient" => {
+                    session.request.resupply_insufficient(value);
+                }
+                "perfect-splits" => session.show_perfect_splits = value,
+                _ => {
+                    return Err(Error::InvalidSyntax {
+                        input: option.to_string(),
+                        expected:
+                            "optimize, reuse-byproducts, resupply-insufficient or perfect-splits",
+                    })
+                }
+            }
         }
-    }
-    product.unsupplied = 0.0;
-}
-
-fn apply_insufficient_supply_proportions(
-    recipes: &IndexedMap<String, Recipe>,
-    product: &mut Product,
-    resupply_proportions: &HashMap<String, f32>,
-) {
-    for (quantity, source) in product.sources.iter_mut() {
-        match source {
-            Source::Recipe { ingredients, .. } => {
-                for ingredient in ingredients.iter_mut() {
-                    apply_insufficient_supply_proportions(
-                        recipes,
-                        ingredient,
-                        resupply_proportions,
-                    );
+        "show" => {
+            let section = match argument.to_lowercase().as_str() {
+                "" => None,
+                "tree" => Some(PlanSection::Tree),
+                "lines" => Some(PlanSection::Lines),
+                "totals" => Some(PlanSection::Totals),
+                "machines" => Some(PlanSection::Machines),
+                _ => {
+                    return Err(Error::InvalidSyntax {
+                        input: argument.to_string(),
+                        expected: "tree, lines, totals or machines",
+                    })
                 }
+            };
+            if session.request.wants().is_empty() {
+                println!("Nothing is wanted yet.");
+                return Ok(Outcome::Done);
             }
-            _ => {
-                resupply_proportions.get(&product.name).map(|proportion| {
-                    let new_quantity = *quantity * proportion;
-                    product.unsupplied = *quantity - new_quantity;
-                    *quantity = new_quantity;
-                });
+            let plan = session.request.plan(recipes)?;
+            let display = plan.display(session.show_perfect_splits);
+            match section {
+                Some(section) => print!("{}", display.section(section)),
+                None => print!("{}", display),
             }
+            return Ok(Outcome::Done);
+        }
+        "list" => {
+            for (product, _) in parse_product_list(argument)? {
+                print_recipes(recipes, &product)?;
+            }
+            return Ok(Outcome::Done);
+        }
+        "uses" => {
+            for (item, _) in parse_product_list(argument)? {
+                print_uses(recipes, &item)?;
+            }
+            return Ok(Outcome::Done);
+        }
+        "undo" => return Ok(Outcome::Undo),
+        "help" => {
+            println!("{}", INTERACTIVE_HELP);
+            return Ok(Outcome::Done);
+        }
+        "quit" | "exit" => return Ok(Outcome::Quit),
+        _ => {
+            return Err(Error::InvalidSyntax {
+                input: command.to_string(),
+                expected: "a command; type `help` for a list of them",
+            })
         }
     }
-    product.sources.retain(|(quantity, _)| *quantity > 0.0);
+    Ok(Outcome::Changed(session))
 }
 
-fn compute_supply_proportions(
-    inputs: &HashMap<String, f32>,
-    ingredients: &HashMap<String, Option<f32>>,
-) -> Vec<(String, f32)> {
-    ingredients
-        .iter()
-        .filter_map(
-            |(ingredient, maybe_quantity)| match inputs.get(ingredient) {
-                Some(ingredient_quantity) => maybe_quantity.map(|available_ingredient_quantity| {
-                    (
-                        ingredient.clone(),
-                        available_ingredient_quantity / ingredient_quantity,
-                    )
-                }),
-                None => None,
-            },
-        )
-        .collect()
+/// Replan a session and print the products it makes & the power it draws
+fn print_summary(recipes: &Recipes, session: &Session) -> Result<(), Error> {
+    if session.request.wants().is_empty() {
+        println!("Nothing is wanted yet.");
+        return Ok(());
+    }
+    let plan = session.request.plan(recipes)?;
+    for (product, quantity) in &plan.totals.outputs {
+        println!(" * {:.2} {}", quantity, product);
+    }
+    println!("Total Power: {:.2} MW", plan.totals.total_power());
+    Ok(())
 }
 
-fn resolve_dependency_trees(
-    recipes: &IndexedMap<String, Recipe>,
-    products: Vec<(String, Option<f32>)>,
-    ingredients: Vec<(String, Option<f32>)>,
-    resupply_insufficient: bool,
-    reuse_byproducts: bool,
-) -> (Vec<Product>, DependencyResolutionTotals) {
-    let mut ingredients = ingredients.into_iter().collect::<HashMap<_, _>>();
-    debug!(&ingredients);
-
-    let mut initial_byproducts = HashMap::new();
-
-    loop {
-        let mut input_byproducts = initial_byproducts.clone();
-        debug!(&input_byproducts);
-
-        // fetch list of requests with specific quantities
-        let quantity_requested_trees = {
-            let mut trees = products
-                .iter()
-                .filter_map(|(name, maybe_quantity)| {
-                    maybe_quantity.map(|quantity| Product {
-                        name: name.clone(),
-                        unsupplied: quantity,
-                        sources: Vec::new(),
-                    })
-                })
-                .collect::<Vec<_>>();
-            debug!(&trees);
-
-            if !trees.is_empty() {
-                let ingredient_set = ingredients.iter().map(|(i, _)| i.clone()).collect();
-                debug!(&ingredient_set);
-
-                for tree in &mut trees {
-                    resolve_product_dependencies(recipes, tree, &ingredient_set, &input_byproducts);
-                }
-                debug!(&trees);
-
-                let mut totals = DependencyResolutionTotals::from(&trees);
-                debug!(&totals);
+/// Convert a product list in command line syntax into one for a plan file
+fn product_rates(raw: &str) -> Result<Vec<ProductRate>, Error> {
+    Ok(parse_product_list(raw)?
+        .into_iter()
+        .map(|(product, rate)| ProductRate { product, rate })
+        .collect())
+}
 
-                let initial_supply_proportions =
-                    compute_supply_proportions(&totals.inputs, &ingredients);
-                debug!(&initial_supply_proportions);
+/// Print a plan in the format asked for
+fn print_plan(args: &Args, plan: &Plan, show_perfect_splits: bool) {
+    // text output shows notes itself, and JSON carries them; graphs have nowhere to put them
+    if args.format == OutputFormat::Dot || args.format == OutputFormat::Mermaid {
+        for note in plan.notes() {
+            eprintln!("Note: {}", note);
+        }
+    }
+    let graph = || match args.merge {
+        true => plan.graph().merged(),
+        false => plan.graph(),
+    };
+    match args.format {
+        OutputFormat::Text => print!("{}", plan.display(show_perfect_splits).merged(args.merge)),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(plan).unwrap()),
+        OutputFormat::Dot => print!("{}", graph().display(GraphFormat::Dot)),
+        OutputFormat::Mermaid => print!("{}", graph().display(GraphFormat::Mermaid)),
+    }
+}
 
-                let mut insufficient_ingredients = initial_supply_proportions
-                    .clone()
-                    .into_iter()
-                    .filter(|(_, proportion)| *proportion < 1.0)
-                    .collect::<HashMap<_, _>>();
-                debug!(&insufficient_ingredients);
+/// Read a plan request, given as a JSON plan file with an optional `id` to tell its result apart
+/// by. The `id` is read even if the rest of the request is invalid
+fn parse_plan_request(
+    source: &str,
+    raw: &str,
+) -> (Option<serde_json::Value>, Result<PlanFile, Error>) {
+    let invalid = |source_error| Error::InvalidConfig {
+        file: source.to_string(),
+        source: source_error,
+    };
+    let mut fields = match serde_json::from_str::<serde_json::Map<_, _>>(raw) {
+        Ok(fields) => fields,
+        Err(err) => return (None, Err(invalid(err))),
+    };
+    let id = fields.remove("id");
+    let plan_file = serde_json::from_value(serde_json::Value::Object(fields)).map_err(invalid);
+    (id, plan_file)
+}
 
-                let byproduct_some_set = input_byproducts
-                    .clone()
-                    .into_iter()
-                    .map(|(byproduct, quantity)| (byproduct, Some(quantity)))
-                    .collect();
+/// The result of a single line of a batch file
+#[derive(Serialize)]
+struct BatchResult {
+    line: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<serde_json::Value>,
+    #[serde(flatten)]
+    outcome: BatchOutcome,
+}
 
-                let mut insufficient_byproduct_inputs =
-                    compute_supply_proportions(&totals.byproduct_inputs, &byproduct_some_set)
-                        .into_iter()
-                        .filter(|(_, proportion)| *proportion < 1.0)
-                        .collect::<HashMap<_, _>>();
-                debug!(&insufficient_byproduct_inputs);
-
-                // adjust output if some provided quantities are insufficient
-                while !insufficient_ingredients.is_empty()
-                    || !insufficient_byproduct_inputs.is_empty()
-                {
-                    if !insufficient_ingredients.is_empty() {
-                        if resupply_insufficient {
-                            // resupply insufficient supplies
-                            let ingredient_set_sans_resupplies = ingredients
-                                .iter()
-                                .filter(|(ingredient, _)| {
-                                    !insufficient_ingredients.contains_key(*ingredient)
-                                })
-                                .map(|(ingredient, _)| ingredient.clone())
-                                .collect();
-                            debug!(&ingredient_set_sans_resupplies);
-
-                            for tree in &mut trees {
-                                apply_insufficient_supply_proportions(
-                                    recipes,
-                                    tree,
-                                    &insufficient_ingredients,
-                                );
-                                debug!(&tree);
-                                resolve_product_dependencies(
-                                    recipes,
-                                    tree,
-                                    &ingredient_set_sans_resupplies,
-                                    &input_byproducts,
-                                );
-                            }
-                            debug!(&trees);
-                        } else {
-                            // adjust output to accommodate for lowest supplied ingredient
-                            let lowest_supply = initial_supply_proportions
-                                .iter()
-                                .map(|(_, quantity)| *quantity)
-                                .min_by(|a, b| {
-                                    a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal)
-                                });
-                            debug!(&lowest_supply);
-
-                            lowest_supply.map(|supply| {
-                                for tree in &mut trees {
-                                    tree.adjust_quantities(supply);
-                                }
-                                debug!(&trees);
-                            });
-                        }
-                    }
-
-                    if !insufficient_byproduct_inputs.is_empty() {
-                        for tree in &mut trees {
-                            apply_insufficient_supply_proportions(
-                                recipes,
-                                tree,
-                                &insufficient_byproduct_inputs,
-                            );
-                            debug!(&tree);
-                            resolve_product_dependencies(
-                                recipes,
-                                tree,
-                                &ingredient_set,
-                                &HashMap::new(),
-                            );
-                        }
-                        debug!(&trees);
-                    }
-
-                    totals = DependencyResolutionTotals::from(&trees);
-                    debug!(&totals);
-
-                    insufficient_ingredients =
-                        compute_supply_proportions(&totals.inputs, &ingredients)
-                            .into_iter()
-                            .filter(|(_, proportion)| *proportion < 1.0)
-                            .collect::<HashMap<_, _>>();
-                    debug!(&insufficient_ingredients);
-
-                    insufficient_byproduct_inputs =
-                        compute_supply_proportions(&totals.byproduct_inputs, &byproduct_some_set)
-                            .into_iter()
-                            .filter(|(_, proportion)| *proportion < 1.0)
-                            .collect::<HashMap<_, _>>();
-                    debug!(&insufficient_byproduct_inputs);
-                }
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum BatchOutcome {
+    Plan(Box<Plan>),
+    Error(String),
+}
 
-                // adjust available ingredients
-                for (ingredient, used_quantity) in totals.inputs.iter() {
-                    if ingredients.contains_key(ingredient) {
-                        ingredients.entry(ingredient.clone()).and_modify(|entry| {
-                            *entry = entry.map(|available_quantity| {
-                                (available_quantity - used_quantity).max(0.0)
-                            })
-                        });
-                    }
-                }
-                debug!(&ingredients);
-
-                // adjust available byproducts
-                for (byproduct, used_quantity) in totals.byproduct_inputs.iter() {
-                    if input_byproducts.contains_key(byproduct) {
-                        input_byproducts
-                            .entry(byproduct.clone())
-                            .and_modify(|entry| {
-                                *entry = (*entry - used_quantity).max(0.0);
-                            });
-                    }
-                }
-                debug!(&input_byproducts);
+/// Plan every request in a batch file, printing each result as it's planned
+fn batch(recipes: &Recipes, file: Option<&str>) -> Result<(), Error> {
+    let name = file.unwrap_or("stdin");
+    let input: Box<dyn BufRead> = match file {
+        Some(file) => Box::new(BufReader::new(File::open(file).map_err(|source| {
+            Error::ReadFailed {
+                file: file.to_string(),
+                source,
             }
-            trees
-        };
-
-        // fetch list of resources without specified quantities
-        let quantity_unrequested_trees = {
-            let mut trees = products
-                .iter()
-                .filter_map(|(name, maybe_quantity)| match maybe_quantity {
-                    None => Some(Product {
-                        name: name.clone(),
-                        unsupplied: recipes
-                            .get(name)
-                            .map(|recipe| {
-                                recipe
-                                    .products
-                                    .iter()
-                                    .find(|(p, _)| p == name)
-                                    .map(|(_, q)| q.clone())
-                            })
-                            .unwrap_or(Some(1.0))
-                            .unwrap_or(1.0),
-                        sources: Vec::new(),
-                    }),
-                    _ => None,
-                })
-                .collect::<Vec<_>>();
-            debug!(&trees);
-
-            if !trees.is_empty() {
-                let ingredient_set = ingredients.iter().map(|(i, _)| i.clone()).collect();
-                debug!(&ingredient_set);
-
-                for tree in &mut trees {
-                    resolve_product_dependencies(recipes, tree, &ingredient_set, &input_byproducts);
-                }
-                debug!(&trees);
-
-                let totals = DependencyResolutionTotals::from(&trees);
-                debug!(&totals);
-
-                let byproduct_some_set = input_byproducts
-                    .clone()
-                    .into_iter()
-                    .map(|(byproduct, quantity)| (byproduct, Some(quantity)))
-                    .collect();
+        })?)),
+        None => Box::new(io::stdin().lock()),
+    };
 
-                let mut supply_proportions: HashMap<String, f32> = HashMap::new();
-                compute_supply_proportions(&totals.inputs, &ingredients)
-                    .into_iter()
-                    .chain(
-                        compute_supply_proportions(&totals.byproduct_inputs, &byproduct_some_set)
-                            .into_iter(),
-                    )
-                    .for_each(|(input, quantity)| {
-                        *supply_proportions.get_default(&input) += quantity;
-                    });
-                debug!(&supply_proportions);
-
-                // adjust output to acommodate for lowest supplied ingredient
-                let lowest_supply = supply_proportions
-                    .iter()
-                    .map(|(_, quantity)| *quantity)
-                    .min_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
-                debug!(&lowest_supply);
-
-                lowest_supply.map(|supply| {
-                    for tree in &mut trees {
-                        tree.adjust_quantities(supply);
-                    }
-                    debug!(&trees);
-                });
+    let mut total = 0;
+    let mut failures = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        let line = line.map_err(|source| Error::ReadFailed {
+            file: name.to_string(),
+            source,
+        })?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        total += 1;
+        let number = i + 1;
+        let (id, plan_file) = parse_plan_request(&format!("{} line {}", name, number), &line);
+        let outcome = plan_file.and_then(|plan_file| plan_file.request().plan(recipes));
+        let outcome = match outcome {
+            Ok(plan) => BatchOutcome::Plan(Box::new(plan)),
+            Err(err) => {
+                failures.push((number, err.to_string()));
+                BatchOutcome::Error(err.to_string())
             }
-            trees
         };
-
-        let trees: Vec<Product> = quantity_requested_trees
-            .into_iter()
-            .chain(quantity_unrequested_trees.into_iter())
-            .collect();
-        debug!(&trees);
-
-        let totals = DependencyResolutionTotals::from(&trees);
-        debug!(&totals);
-
-        debug!(&initial_byproducts);
-        if reuse_byproducts && totals.byproducts != initial_byproducts {
-            initial_byproducts = totals.byproducts;
-        } else {
-            return (trees, totals);
-        }
+        let result = BatchResult {
+            line: number,
+            id,
+            outcome,
+        };
+        println!("{}", serde_json::to_string(&result).unwrap());
     }
-}
 
-fn find_product_name(products: &HashSet<String>, name: &String) -> String {
-    let name = name.trim().to_lowercase();
-    products
-        .iter()
-        .find(|full_name| full_name.to_lowercase() == name)
-        .unwrap_or(&name)
-        .clone()
+    // summarize failures apart from the results, so they can still be read line by line
+    if failures.is_empty() {
+        eprintln!("Planned all {} requests.", total);
+        return Ok(());
+    }
+    eprintln!("Failed requests:");
+    for (number, err) in &failures {
+        eprintln!(" * line {}: {}", number, err);
+    }
+    Err(Error::BatchFailed {
+        failed: failures.len(),
+        total,
+    })
 }
 
-fn parse_product_list(products: &HashSet<String>, raw: &String) -> Vec<(String, Option<f32>)> {
-    let part_pattern = Regex::new(r"^([^:]*)(:\s*(\d+(\.\d+)?|\.\d+))?$").unwrap();
-    raw.split(",")
-        .map(
-            |part| match part_pattern.captures(part.trim().to_lowercase().as_str()) {
-                None => panic!("'{part}' is invalid!"),
-                Some(captures) => (
-                    find_product_name(products, &captures.get(1).unwrap().as_str().to_string()),
-                    captures.get(3).map(|m| m.as_str().parse().unwrap()),
-                ),
-            },
-        )
-        .collect()
+/// Answer API requests until the process is stopped
+fn serve(recipes: &Recipes, host: &str, port: u16) -> Result<(), Error> {
+    let address = format!("{}:{}", host, port);
+    let server = Server::http(&address).map_err(|source| Error::ServeFailed {
+        address: address.clone(),
+        source,
+    })?;
+    println!("Listening on http://{}", address);
+
+    for mut request in server.incoming_requests() {
+        let (status, body) = match api_response(recipes, &mut request) {
+            Ok(body) => (200, body),
+            Err((status, message)) => (status, serde_json::json!({ "error": message }).to_string()),
+        };
+        let url = request.url().to_string();
+        println!("{} {} {}", request.method(), url, status);
+        let response = Response::from_string(body)
+            .with_status_code(status)
+            .with_header(Header::from_bytes("Content-Type", "application/json").unwrap());
+        if let Err(err) = request.respond(response) {
+            eprintln!("Error: couldn't respond to {}: {}", url, err);
+        }
+    }
+    Ok(())
 }
 
-fn parse_product_index_list(products: &HashSet<String>, raw: &String) -> HashMap<String, usize> {
-    let part_pattern = Regex::new(r"([^:]*):\s*(\d+)").unwrap();
-    raw.split(",")
-        .map(
-            |part| match part_pattern.captures(part.trim().to_lowercase().as_str()) {
-                None => panic!("'{part}' is invalid!"),
-                Some(captures) => (
-                    find_product_name(products, &captures.get(1).unwrap().as_str().to_string()),
-                    captures
-                        .get(2)
-                        .map(|m| m.as_str().parse().unwrap())
-                        .unwrap(),
-                ),
-            },
-        )
-        .collect()
+/// Answer a single API request with JSON, or with a status code & the reason it failed
+fn api_response(recipes: &Recipes, request: &mut Request) -> Result<String, (u16, String)> {
+    let url = request.url().to_string();
+    let path = url.split('?').next().unwrap_or_default();
+    let segments: Vec<_> = path.trim_matches('/').split('/').collect();
+    match (request.method(), segments.as_slice()) {
+        (Method::Get, ["products"]) => Ok(serde_json::to_string(&recipes.products()).unwrap()),
+        (Method::Get, ["recipes", product]) => {
+            let product = urlencoding::decode(product).map_err(|err| (400, err.to_string()))?;
+            let product = recipes
+                .product_name(&product)
+                .map_err(|err| (404, err.to_string()))?;
+            Ok(serde_json::to_string(recipes.recipes_for(&product)).unwrap())
+        }
+        (Method::Post, ["plan"]) => {
+            let mut body = String::new();
+            request
+                .as_reader()
+                .read_to_string(&mut body)
+                .map_err(|err| (400, err.to_string()))?;
+            // validated the same way as a batch line, so anything a batch rejects is a bad request
+            let (_, plan_file) = parse_plan_request("the request", &body);
+            let plan_file = plan_file.map_err(|err| (400, err.to_string()))?;
+            let plan = plan_file.request().plan(recipes).map_err(|err| match err {
+                Error::NothingWanted => (400, err.to_string()),
+                Error::UnknownProduct { .. } => (404, err.to_string()),
+                err => (422, err.to_string()),
+            })?;
+            Ok(serde_json::to_string(&plan).unwrap())
+        }
+        (_, ["products"] | ["recipes", _] | ["plan"]) => Err((
+            405,
+            format!("{} isn't supported by {}", request.method(), path),
+        )),
+        _ => Err((404, format!("{} isn't an API endpoint", path))),
+    }
 }
 
-fn load_recipes(file: &str) -> (IndexedMap<String, Recipe>, HashSet<String>) {
-    let recipe_list = serde_json::from_str::<Vec<Recipe>>(
-        fs::read_to_string(file)
-            .expect(format!("{} not found!", file).as_str())
-            .as_str(),
-    )
-    .expect(format!("{} is in an invalid format!", file).as_str());
-
-    let recipe_map = recipe_list
-        .clone()
-        .into_iter()
-        .map(|recipe| {
-            recipe
-                .products
-                .iter()
-                .map(|(product, _)| (product.clone(), recipe.clone()))
-                .collect::<Vec<_>>()
-        })
-        .flatten()
-        .into();
-
-    let ingredient_set = recipe_list
-        .into_iter()
-        .map(|recipe| {
-            recipe
-                .ingredients
-                .into_iter()
-                .map(|(ingredient, _)| ingredient)
-                .chain(
-                    recipe
-                        .products
-                        .into_iter()
-                        .map(|(product, _)| product)
-                        .collect::<Vec<_>>(),
-                )
-        })
-        .flatten()
-        .collect();
+fn run(args: Args) -> Result<(), Error> {
+    // recipes are only loaded by the commands that plan with them
+    let load_recipes = || {
+        RecipesLoader::new()
+            .recipe_configs(&args.recipe_config)
+            .machine_config(&args.machine_config)
+            .generator_config(&args.generator_config)
+            .item_config(&args.item_config)
+            .profile(&args.profile)
+            .alias_config(&args.alias_config)
+            .abbreviations(args.abbreviations)
+            .load()
+    };
 
-    (recipe_map, ingredient_set)
+    match &args.command {
+        Command::Plan {
+            command:
+                PlanCommand::Save {
+                    file,
+                    want,
+                    have,
+                    recipes,
+                    show_perfect_splits,
+                    resupply_insufficient,
+                    reuse_byproducts,
+                },
+        } => {
+            // plan files are written without loading recipes, so names are checked when they're run
+            let plan_file = PlanFile {
+                want: product_rates(want)?,
+                have: have
+                    .as_deref()
+                    .map(product_rates)
+                    .transpose()?
+                    .unwrap_or_default(),
+                recipes: match recipes {
+                    Some(recipes) => parse_recipe_selections(recipes)?.into_iter().collect(),
+                    None => BTreeMap::new(),
+                },
+                show_perfect_splits: *show_perfect_splits,
+                resupply_insufficient: *resupply_insufficient,
+                reuse_byproducts: *reuse_byproducts,
+            };
+            plan_file.write(file)?;
+            println!("Saved plan to {}.", file);
+            Ok(())
+        }
+        Command::ImportDocs { docs } => {
+            // regenerate configs from the game's own data
+            if args.recipe_config.len() > 1 {
+                return Err(Error::MultipleRecipeConfigs);
+            }
+            let (recipes, machines, items) = import_docs(docs)?;
+            write_config(&args.recipe_config[0], &recipes)?;
+            write_config(&args.machine_config, &machines)?;
+            write_config(&args.item_config, &items)?;
+            println!(
+                "Imported {} recipes, {} machines and {} items.",
+                recipes.len(),
+                machines.len(),
+                items.len()
+            );
+            Ok(())
+        }
+        Command::Interactive => interactive(&load_recipes()?),
+        Command::Batch { file } => batch(&load_recipes()?, file.as_deref()),
+        Command::Serve { port, host } => serve(&load_recipes()?, host, *port),
+        Command::Buildable {
+            have,
+            recipes: recipe_overrides,
+            reuse_byproducts,
+            optimize,
+        } => {
+            let recipes = load_recipes()?;
+            let mut request = PlanRequest::new();
+            for (ingredient, rate) in parse_product_list(have)? {
+                request.have(product_name(&recipes, &ingredient)?, rate);
+            }
+            if let Some(recipe_overrides) = recipe_overrides {
+                for (product, recipe) in parse_recipe_selections(recipe_overrides)? {
+                    request.use_recipe(product, recipe);
+                }
+            }
+            request
+                .reuse_byproducts(*reuse_byproducts)
+                .optimize(*optimize);
+            print_buildable(&args, &recipes, &request)
+        }
+        Command::Plan {
+            command: PlanCommand::Run { file },
+        } => {
+            let recipes = load_recipes()?;
+            let plan_file = PlanFile::read(file)?;
+            let plan = plan_file.request().plan(&recipes)?;
+            print_plan(&args, &plan, plan_file.show_perfect_splits);
+            Ok(())
+        }
+        Command::Make(make_args) => make(&args, make_args, &load_recipes()?),
+    }
 }
 
-/// Satisfactory Factory Planning Utility
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-struct Args {
-    /// Product(s) to create, in the form `<name>[:rate][,<name>[:rate][...]]` etc.
-    want: String,
-
-    /// Ingredients that you have access to, in the form `<name>[:rate][,<name>[:rate][...]]` etc.
-    have: Option<String>,
-
-    /// Convert final machine counts to perfect split whole numbers, and list the underclocks for them
-    #[arg(long, short = 'p', action = ArgAction::SetTrue)]
-    show_perfect_splits: bool,
-
-    /// If not enough input resources are available, then resupply more to fulfill the requested quota, instead of limiting the output totals
-    #[arg(long, short = 's', action = ArgAction::SetTrue)]
-    resupply_insufficient: bool,
+/// Plan & display a factory, or list the recipes for or using products, for the `make` command
+fn make(args: &Args, make_args: &MakeArgs, recipes: &Recipes) -> Result<(), Error> {
+    if let Some(uses) = &make_args.uses {
+        // list all recipes that consume the passed items
+        let items = parse_product_list(uses)?;
+        if args.format == OutputFormat::Json {
+            let mut item_uses = BTreeMap::new();
+            for (item, _) in items {
+                let item = product_name(recipes, &item)?;
+                let uses: Vec<_> = recipes
+                    .recipes_using(&item)
+                    .into_iter()
+                    .map(|(product, index, details)| RecipeUse {
+                        product,
+                        recipe: index + 1,
+                        details,
+                    })
+                    .collect();
+                item_uses.insert(item, uses);
+            }
+            println!("{}", serde_json::to_string_pretty(&item_uses).unwrap());
+        } else {
+            for (item, _) in items {
+                print_uses(recipes, &item)?;
+            }
+        }
+        return Ok(());
+    }
 
-    /// Specify a custom config file for crafting recipes
-    #[arg(long, short = 'c', default_value = "recipes.json")]
-    recipe_config: String,
+    // parse lists of desired outputs
+    let want_list = parse_product_list(make_args.want.as_ref().unwrap())?;
 
-    /// List all recipes that produce the given product
-    #[arg(long, short = 'l', action = ArgAction::SetTrue)]
-    list_recipes: bool,
+    if make_args.list_recipes && args.format == OutputFormat::Json {
+        let mut recipe_lists = BTreeMap::new();
+        for (product, _) in want_list {
+            let product = product_name(recipes, &product)?;
+            let recipe_set = recipes.recipes_for(&product);
+            recipe_lists.insert(product, recipe_set);
+        }
+        println!("{}", serde_json::to_string_pretty(&recipe_lists).unwrap());
+        return Ok(());
+    }
 
-    /// Provide overrides to existing recipes by passing a list of products and the associated recipe index to use to manufacture said product.
-    /// Syntax is name:index[,name:index[,...]]
-    #[arg(long, short = 'r')]
-    recipes: Option<String>,
+    if make_args.list_recipes {
+        // list all recipes for the passed product
+        for (product, _) in want_list {
+            print_recipes(recipes, &product)?;
+        }
+        return Ok(());
+    }
 
-    /// !! EXPERIMENTAL !! Allow the reuse of byproduct outputs from the system as inputs
-    #[arg(long, short = 'b', action = ArgAction::SetTrue)]
-    reuse_byproducts: bool,
+    // build the plan request
+    let mut request = PlanRequest::new();
+    for (product, rate) in want_list {
+        request.want(product, rate);
+    }
+    if let Some(have) = &make_args.have {
+        for (ingredient, rate) in parse_product_list(have)? {
+            request.have(ingredient, rate);
+        }
+    }
+    if let Some(recipe_overrides) = &make_args.recipes {
+        for (product, recipe) in parse_recipe_selections(recipe_overrides)? {
+            request.use_recipe(product, recipe);
+        }
+    }
+    if let Some(clock) = &make_args.clock {
+        request.clock_speeds(parse_clock_speeds(clock)?);
+    }
+    if let Some(nodes) = &make_args.nodes {
+        for (resource, resource_nodes) in parse_resource_nodes(nodes)? {
+            request.resource_nodes(resource, resource_nodes);
+        }
+    }
+    if let Some(max_belt) = make_args.max_belt {
+        request.max_belt(max_belt);
+    }
+    if let Some(max_pipe) = make_args.max_pipe {
+        request.max_pipe(max_pipe);
+    }
+    request
+        .resupply_insufficient(make_args.resupply_insufficient)
+        .reuse_byproducts(make_args.reuse_byproducts)
+        .optimize(make_args.optimize)
+        .extraction(make_args.extraction)
+        .miner(make_args.miner)
+        .throughput(make_args.throughput);
+
+    // plan & display the factory
+    let plan = request.plan(recipes)?;
+    print_plan(args, &plan, make_args.show_perfect_splits);
+
+    Ok(())
 }
 
-fn main() {
-    // parse arguments
-    #[cfg(not(debug_assertions))]
-    let args = Args::parse();
-    #[cfg(debug_assertions)]
-    let args = Args::parse_from(vec!["_", "gas filter"]);
-
-    // compute recipe map
-    let (mut recipes, product_set) = load_recipes(&args.recipe_config);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // parse lists of desired outputs
-    let want_list = parse_product_list(&product_set, &args.want);
+    fn parse(args: &[&str]) -> Args {
+        let args = ["satisfactory_factory_planner"]
+            .iter()
+            .chain(args)
+            .map(|arg| arg.to_string())
+            .collect();
+        Args::try_parse_from(with_default_command(args)).unwrap()
+    }
 
-    if args.list_recipes {
-        // list all recipes for the passed product
-        for (product, _) in want_list {
-            println!("{}:", product);
-            match recipes.map.get(&product) {
-                None => println!(" * No recipes for this product."),
-                Some(recipe_set) => {
-                    for (i, recipe) in recipe_set.iter().enumerate() {
-                        println!(" {}.", i + 1);
-                        println!("    Ingredients:");
-                        for (ingredient, quantity) in recipe.ingredients.iter() {
-                            println!("     - {:.2} {}/min", quantity, ingredient);
-                        }
-                        println!("    Products:");
-                        for (product, quantity) in recipe.products.iter() {
-                            println!("     - {:.2} {}/min", quantity, product);
-                        }
-                        println!();
-                    }
+    #[test]
+    fn global_options_before_commands() {
+        let globals = [
+            "-c",
+            "a.json",
+            "--recipe-config=b.json",
+            "-m",
+            "machines.json",
+            "--format",
+            "json",
+            "--merge",
+            "-a",
+        ];
+        let commands: [(&str, &[&str]); 9] = [
+            ("make", &["computer:5"]),
+            ("make", &["make", "computer:5"]),
+            ("import-docs", &["import-docs", "Docs.json"]),
+            ("interactive", &["interactive"]),
+            ("batch", &["batch", "f.jsonl"]),
+            ("buildable", &["buildable", "iron ore:60"]),
+            ("serve", &["serve", "--port", "80"]),
+            ("plan", &["plan", "run", "x.toml"]),
+            ("plan", &["plan", "save", "x.toml", "computer:5"]),
+        ];
+        for (expected, command) in commands {
+            let args = parse(&[&globals[..], command].concat());
+            assert_eq!(args.recipe_config, ["a.json", "b.json"], "{:?}", command);
+            assert_eq!(args.format, OutputFormat::Json, "{:?}", command);
+            assert!(args.merge && args.abbreviations, "{:?}", command);
+            let name = match args.command {
+                Command::Make(make_args) => {
+                    assert_eq!(make_args.want.as_deref(), Some("computer:5"));
+                    "make"
                 }
-            }
-        }
-    } else {
-        // parse list of available inputs
-        let have_list = args.have.map_or_else(
-            || Vec::new(),
-            |have| parse_product_list(&product_set, &have),
-        );
-
-        // read recipe overrides
-        args.recipes.map(|recipe_overrides| {
-            recipes.index.extend(
-                parse_product_index_list(&product_set, &recipe_overrides)
-                    .into_iter()
-                    .map(|(product, index)| (product, index - 1)),
-            );
-        });
-
-        // Compute recipe dependencies
-        let (tree, totals) = resolve_dependency_trees(
-            &recipes,
-            want_list,
-            have_list,
-            args.resupply_insufficient,
-            args.reuse_byproducts,
-        );
-
-        // Display tree
-        println!();
-        println!("Tree:");
-        for node in tree {
-            println!("{node}");
+                Command::ImportDocs { .. } => "import-docs",
+                Command::Interactive => "interactive",
+                Command::Batch { .. } => "batch",
+                Command::Buildable { .. } => "buildable",
+                Command::Serve { .. } => "serve",
+                Command::Plan { .. } => "plan",
+            };
+            assert_eq!(name, expected, "{:?}", command);
         }
+    }
 
-        // Display totals
-        println!(
-            "{}",
-            DependencyResolutionTotalsDisplay {
-                totals: &totals,
-                show_perfect_splits: args.show_perfect_splits
-            }
-        );
+    #[test]
+    fn planning_options_before_wanted_products() {
+        let args = parse(&[
+            "-k150",
+            "-r",
+            "screw:2",
+            "-bo",
+            "--miner",
+            "mk2",
+            "computer:5",
+            "copper ingot",
+        ]);
+        let Command::Make(make_args) = args.command else {
+            panic!("expected the make command");
+        };
+        assert_eq!(make_args.clock.as_deref(), Some("150"));
+        assert_eq!(make_args.recipes.as_deref(), Some("screw:2"));
+        assert!(make_args.reuse_byproducts && make_args.optimize);
+        assert_eq!(make_args.want.as_deref(), Some("computer:5"));
+        assert_eq!(make_args.have.as_deref(), Some("copper ingot"));
+
+        let args = parse(&["--uses", "water"]);
+        assert!(matches!(
+            args.command,
+            Command::Make(MakeArgs { uses: Some(_), .. })
+        ));
     }
 }
@@ -0,0 +1,177 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use satisfactory_factory_planner::{
+    import_docs, write_config, ItemForm, PlanRequest, Recipe, RecipesLoader,
+};
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/Docs.json");
+
+/// A fresh directory to write configs into, unique to the test
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "satisfactory_factory_planner_{}_{}",
+        name,
+        std::process::id()
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn path(dir: &Path, file: &str) -> String {
+    dir.join(file).to_string_lossy().into_owned()
+}
+
+fn recipe<'a>(recipes: &'a [Recipe], name: &str) -> &'a Recipe {
+    recipes
+        .iter()
+        .find(|recipe| recipe.name.as_deref() == Some(name))
+        .unwrap_or_else(|| panic!("no recipe named {}", name))
+}
+
+#[test]
+fn reads_utf16_docs_the_same_as_utf8() {
+    let dir = scratch_dir("utf16");
+    let docs = fs::read_to_string(FIXTURE).unwrap();
+    // the game ships Docs.json as UTF-16 with a byte order mark
+    let little_endian = [0xFF, 0xFE]
+        .into_iter()
+        .chain(docs.encode_utf16().flat_map(u16::to_le_bytes))
+        .collect::<Vec<u8>>();
+    let big_endian = [0xFE, 0xFF]
+        .into_iter()
+        .chain(docs.encode_utf16().flat_map(u16::to_be_bytes))
+        .collect::<Vec<u8>>();
+    fs::write(dir.join("le.json"), little_endian).unwrap();
+    fs::write(dir.join("be.json"), big_endian).unwrap();
+
+    let utf8 = serde_json::to_value(import_docs(FIXTURE).unwrap()).unwrap();
+    for file in ["le.json", "be.json"] {
+        let utf16 = serde_json::to_value(import_docs(&path(&dir, file)).unwrap()).unwrap();
+        assert_eq!(utf16, utf8, "{} was decoded differently", file);
+    }
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn malformed_amounts_are_errors() {
+    let dir = scratch_dir("amounts");
+    let docs = fs::read_to_string(FIXTURE)
+        .unwrap()
+        .replace("Amount=13", "Amount=.");
+    fs::write(dir.join("Docs.json"), docs).unwrap();
+
+    let error = import_docs(&path(&dir, "Docs.json")).unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .ends_with("gives Iron Ingot an amount of '.', which isn't a number"),
+        "{}",
+        error
+    );
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn imports_recipes_machines_and_items() {
+    let (recipes, machines, items) = import_docs(FIXTURE).unwrap();
+
+    // the build gun recipe for the Smelter is left out, as it isn't made in a production machine
+    let mut names: Vec<_> = recipes
+        .iter()
+        .filter_map(|recipe| recipe.name.as_deref())
+        .collect();
+    names.sort();
+    assert_eq!(
+        names,
+        [
+            "Alternate: Pure Iron Ingot",
+            "Iron Ingot",
+            "Iron Plate",
+            "Plutonium Pellet"
+        ]
+    );
+
+    // amounts are per minute, with fluids scaled from liters down to cubic meters
+    let pure_iron = recipe(&recipes, "Alternate: Pure Iron Ingot");
+    assert_eq!(pure_iron.machine, "Refinery");
+    assert_eq!(
+        pure_iron.ingredients,
+        [("Iron Ore".to_string(), 35.0), ("Water".to_string(), 20.0)]
+    );
+    assert_eq!(pure_iron.products, [("Iron Ingot".to_string(), 65.0)]);
+    assert_eq!(pure_iron.power, None);
+
+    // variable power recipes draw the average over their power cycle
+    let plutonium = recipe(&recipes, "Plutonium Pellet");
+    assert_eq!(plutonium.machine, "Particle Accelerator");
+    assert_eq!(plutonium.power, Some(500.0));
+
+    let iron_plate = recipe(&recipes, "Iron Plate");
+    assert_eq!(iron_plate.machine, "Constructor");
+    assert_eq!(iron_plate.unlock.as_deref(), Some("Tier 1 - Base Building"));
+    assert_eq!(recipe(&recipes, "Iron Ingot").unlock, None);
+
+    // only manufacturers are machines; the variable power class isn't mistaken for the plain one,
+    // or the other way around
+    let machines: Vec<_> = machines
+        .iter()
+        .map(|machine| (machine.name.as_str(), machine.power))
+        .collect();
+    assert_eq!(
+        machines,
+        [
+            ("Constructor", 4.0),
+            ("Particle Accelerator", 500.0),
+            ("Refinery", 30.0),
+            ("Smelter", 4.0)
+        ]
+    );
+
+    // buildings have item descriptors too, but aren't items
+    assert!(items.iter().all(|item| item.name != "Smelter"));
+    let water = items.iter().find(|item| item.name == "Water").unwrap();
+    assert_eq!((water.form, water.stack_size), (ItemForm::Liquid, 50000));
+    let nitrogen = items
+        .iter()
+        .find(|item| item.name == "Nitrogen Gas")
+        .unwrap();
+    assert_eq!(nitrogen.form, ItemForm::Gas);
+}
+
+#[test]
+fn imported_configs_load_and_plan() {
+    let dir = scratch_dir("configs");
+    let (recipes, machines, items) = import_docs(FIXTURE).unwrap();
+    write_config(&path(&dir, "recipes.json"), &recipes).unwrap();
+    write_config(&path(&dir, "machines.json"), &machines).unwrap();
+    write_config(&path(&dir, "items.json"), &items).unwrap();
+
+    // import-docs doesn't write a generator config, so none is read
+
+    let loaded = RecipesLoader::new()
+        .recipe_configs([path(&dir, "recipes.json")])
+        .machine_config(path(&dir, "machines.json"))
+        .item_config(path(&dir, "items.json"))
+        .generator_config(path(&dir, "generators.json"))
+        .alias_config(path(&dir, "aliases.json"))
+        .profile(path(&dir, "profile.json"))
+        .load()
+        .unwrap();
+    assert_eq!(
+        loaded.recipes_for("Iron Ingot")[1].name.as_deref(),
+        Some("Alternate: Pure Iron Ingot")
+    );
+
+    let plan = PlanRequest::new()
+        .want("iron plate", Some(20.0))
+        .plan(&loaded)
+        .unwrap();
+    assert_eq!(plan.totals.inputs["Iron Ore"], 30.0);
+    assert_eq!(plan.totals.machines["Constructor"]["Iron Plate"], 1.0);
+    assert_eq!(plan.totals.machines["Smelter"]["Iron Ingot"], 1.0);
+    fs::remove_dir_all(dir).unwrap();
+}